@@ -1,7 +1,140 @@
 use anyhow::Result;
-use dialoguer::{theme::ColorfulTheme, Select};
+use clap::Subcommand;
+use dialoguer::{theme::ColorfulTheme, Input, Select};
 use console::{style, Term};
 
+/// `drovity accounts ...`
+#[derive(Subcommand)]
+pub enum AccountCommand {
+    /// List accounts in rotation order
+    List,
+    /// Put an account back into rotation
+    Enable {
+        /// Position, id, email or label
+        account: String,
+    },
+    /// Take an account out of rotation without deleting its refresh token
+    Disable {
+        /// Position, id, email or label
+        account: String,
+    },
+    /// Set the label shown instead of the email (omit to clear)
+    Label {
+        /// Position, id, email or label
+        account: String,
+        label: Option<String>,
+    },
+    /// Move an account to a position in the rotation (1 = first)
+    Move {
+        /// Position, id, email or label
+        account: String,
+        position: usize,
+    },
+    /// Set the tier used by the `priority` strategy (lower is used first)
+    Priority {
+        /// Position, id, email or label
+        account: String,
+        #[arg(allow_negative_numbers = true)]
+        priority: i64,
+    },
+    /// Set the share used by the `weighted` strategy
+    Weight {
+        /// Position, id, email or label
+        account: String,
+        weight: u32,
+    },
+    /// Set the groups used by `model_routes` (none clears them)
+    Groups {
+        /// Position, id, email or label
+        account: String,
+        groups: Vec<String>,
+    },
+    /// Sign in again for an account whose refresh token was revoked
+    Reauth {
+        /// Position, id, email or label
+        account: String,
+    },
+}
+
+pub async fn run_account_command(command: AccountCommand) -> Result<()> {
+    use crate::config::account;
+    
+    match command {
+        AccountCommand::List => {}
+        AccountCommand::Enable { account: query } => {
+            let acc = account::set_disabled(&account::find_account(&query)?.id, false)?;
+            println!("{}", style(format!("[SUCCESS] {} enabled", acc.name())).green());
+        }
+        AccountCommand::Disable { account: query } => {
+            let acc = account::set_disabled(&account::find_account(&query)?.id, true)?;
+            println!("{}", style(format!("[SUCCESS] {} disabled", acc.name())).green());
+        }
+        AccountCommand::Label { account: query, label } => {
+            let acc = account::set_label(&account::find_account(&query)?.id, label)?;
+            println!("{}", style(format!("[SUCCESS] {} labelled '{}'", acc.email, acc.name())).green());
+        }
+        AccountCommand::Move { account: query, position } => {
+            let acc = account::find_account(&query)?;
+            account::move_account(&acc.id, position.saturating_sub(1))?;
+            println!("{}", style(format!("[SUCCESS] {} moved to position {}", acc.name(), position.max(1))).green());
+        }
+        AccountCommand::Priority { account: query, priority } => {
+            let acc = account::set_priority(&account::find_account(&query)?.id, priority)?;
+            println!("{}", style(format!("[SUCCESS] {} priority set to {}", acc.name(), acc.priority)).green());
+        }
+        AccountCommand::Weight { account: query, weight } => {
+            let acc = account::set_weight(&account::find_account(&query)?.id, weight)?;
+            println!("{}", style(format!("[SUCCESS] {} weight set to {}", acc.name(), acc.weight)).green());
+        }
+        AccountCommand::Groups { account: query, groups } => {
+            let acc = account::set_groups(&account::find_account(&query)?.id, groups)?;
+            let groups = if acc.groups.is_empty() { "none".to_string() } else { acc.groups.join(", ") };
+            println!("{}", style(format!("[SUCCESS] {} groups: {}", acc.name(), groups)).green());
+        }
+        AccountCommand::Reauth { account: query } => {
+            let acc = crate::oauth::reauthorize_account(&account::find_account(&query)?).await?;
+            println!("{}", style(format!("[SUCCESS] {} re-authorized", acc.name())).green());
+        }
+    }
+    
+    print_accounts(&account::list_accounts()?);
+    Ok(())
+}
+
+fn print_accounts(accounts: &[crate::config::account::Account]) {
+    if accounts.is_empty() {
+        println!("{}", style("No accounts added yet.").yellow());
+        return;
+    }
+    
+    println!("{}", style("Current Accounts:").yellow());
+    for (i, account) in accounts.iter().enumerate() {
+        let status = if account.needs_reauth.is_some() {
+            style("[NEEDS RE-AUTH]").red().bold()
+        } else if account.disabled {
+            style("[DISABLED]").red()
+        } else {
+            style("[ACTIVE]").green()
+        };
+        match &account.label {
+            Some(label) => println!("  {}. {} <{}> - {}", i + 1, label, account.email, status),
+            None => println!("  {}. {} - {}", i + 1, account.email, status),
+        }
+        if let Some(reauth) = &account.needs_reauth {
+            println!("     {} {}", style("Refresh token rejected:").dim(), style(&reauth.reason).red());
+        }
+        if !account.groups.is_empty() {
+            println!("     {} {}", style("Groups:").dim(), style(account.groups.join(", ")).cyan());
+        }
+        if account.priority != 0 || account.weight != 1 {
+            println!("     {} {}  {} {}", style("Priority:").dim(), account.priority, style("Weight:").dim(), account.weight);
+        }
+        if let Some(project) = &account.project_id_override {
+            println!("     {} {}", style("Project (pinned):").dim(), style(project).cyan());
+        }
+    }
+}
+
 pub async fn show_accounts_menu() -> Result<()> {
     let term = Term::stdout();
     
@@ -16,26 +149,18 @@ pub async fn show_accounts_menu() -> Result<()> {
         // Load and display accounts
         let accounts = crate::config::account::list_accounts()?;
         
-        if !accounts.is_empty() {
-            println!("{}", style("Current Accounts:").yellow());
-            for (i, account) in accounts.iter().enumerate() {
-                let status = if account.disabled {
-                    style("[DISABLED]").red()
-                } else {
-                    style("[ACTIVE]").green()
-                };
-                println!("  {}. {} - {}", i + 1, account.email, status);
-            }
-            println!();
-        } else {
-            println!("{}", style("No accounts added yet.").yellow());
-            println!();
-        }
+        print_accounts(&accounts);
+        println!();
 
         let choices = vec![
             "1. Add New Account",
             "2. Remove Account",
-            "3. Back to Main Menu",
+            "3. Set Project Override",
+            "4. Enable / Disable Account",
+            "5. Set Label",
+            "6. Reorder Account",
+            "7. Re-authorize Account",
+            "8. Back to Main Menu",
         ];
 
         let selection = Select::with_theme(&ColorfulTheme::default())
@@ -59,6 +184,23 @@ pub async fn show_accounts_menu() -> Result<()> {
                 }
             }
             2 => {
+                // Pin project_id
+                if !accounts.is_empty() {
+                    set_project_override(&accounts).await?;
+                } else {
+                    println!("{}", style("No accounts configured!").red());
+                    term.read_key()?;
+                }
+            }
+            3..=6 if accounts.is_empty() => {
+                println!("{}", style("No accounts configured!").red());
+                term.read_key()?;
+            }
+            3 => toggle_account(&accounts)?,
+            4 => set_account_label(&accounts)?,
+            5 => reorder_account(&accounts)?,
+            6 => reauthorize_account(&accounts).await?,
+            7 => {
                 // Back
                 break;
             }
@@ -141,3 +283,174 @@ async fn remove_account(accounts: &[crate::config::account::Account]) -> Result<
 
     Ok(())
 }
+
+async fn set_project_override(accounts: &[crate::config::account::Account]) -> Result<()> {
+    let term = Term::stdout();
+    
+    let mut choices: Vec<String> = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, acc)| format!("{}. {}", i + 1, acc.email))
+        .collect();
+    choices.push("Cancel".to_string());
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select account")
+        .items(&choices)
+        .default(0)
+        .interact_on(&term)?;
+
+    if selection < accounts.len() {
+        let mut account = accounts[selection].clone();
+        
+        let current = account.project_id_override.clone().unwrap_or_default();
+        let input: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Project ID (leave empty to auto-detect)")
+            .with_initial_text(current)
+            .allow_empty(true)
+            .interact_text_on(&term)?;
+        
+        let input = input.trim().to_string();
+        account.project_id_override = if input.is_empty() { None } else { Some(input) };
+        account.updated_at = chrono::Utc::now().timestamp();
+        crate::config::account::save_account(&account)?;
+        
+        println!();
+        match &account.project_id_override {
+            Some(project) => println!("{}", style(format!("[SUCCESS] {} pinned to project {}", account.email, project)).green()),
+            None => println!("{}", style(format!("[SUCCESS] {} will auto-detect its project", account.email)).green()),
+        }
+        println!("{}", style("A running proxy picks up the change automatically").dim());
+        println!();
+        println!("{}", style("Press any key to continue...").dim());
+        term.read_key()?;
+    }
+
+    Ok(())
+}
+
+/// Menu entry for one account per line, plus Cancel
+fn account_choices(accounts: &[crate::config::account::Account]) -> Vec<String> {
+    let mut choices: Vec<String> = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, acc)| {
+            let state = if acc.needs_reauth.is_some() {
+                " [NEEDS RE-AUTH]"
+            } else if acc.disabled {
+                " [DISABLED]"
+            } else {
+                ""
+            };
+            format!("{}. {}{}", i + 1, acc.name(), state)
+        })
+        .collect();
+    choices.push("Cancel".to_string());
+    choices
+}
+
+fn toggle_account(accounts: &[crate::config::account::Account]) -> Result<()> {
+    let term = Term::stdout();
+    
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select account to enable / disable")
+        .items(&account_choices(accounts))
+        .default(0)
+        .interact_on(&term)?;
+    
+    if let Some(account) = accounts.get(selection) {
+        let account = crate::config::account::set_disabled(&account.id, !account.disabled)?;
+        let state = if account.disabled { "disabled" } else { "enabled" };
+        
+        println!();
+        println!("{}", style(format!("[SUCCESS] {} {}", account.name(), state)).green());
+        println!();
+        println!("{}", style("Press any key to continue...").dim());
+        term.read_key()?;
+    }
+    
+    Ok(())
+}
+
+fn set_account_label(accounts: &[crate::config::account::Account]) -> Result<()> {
+    let term = Term::stdout();
+    
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select account")
+        .items(&account_choices(accounts))
+        .default(0)
+        .interact_on(&term)?;
+    
+    if let Some(account) = accounts.get(selection) {
+        let input: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Label (leave empty to show the email)")
+            .with_initial_text(account.label.clone().unwrap_or_default())
+            .allow_empty(true)
+            .interact_text_on(&term)?;
+        
+        let account = crate::config::account::set_label(&account.id, Some(input))?;
+        
+        println!();
+        println!("{}", style(format!("[SUCCESS] {} is now shown as {}", account.email, account.name())).green());
+        println!();
+        println!("{}", style("Press any key to continue...").dim());
+        term.read_key()?;
+    }
+    
+    Ok(())
+}
+
+fn reorder_account(accounts: &[crate::config::account::Account]) -> Result<()> {
+    let term = Term::stdout();
+    
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select account to move")
+        .items(&account_choices(accounts))
+        .default(0)
+        .interact_on(&term)?;
+    
+    if let Some(account) = accounts.get(selection) {
+        let positions: Vec<String> = (1..=accounts.len()).map(|i| format!("Position {}", i)).collect();
+        let position = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Move to")
+            .items(&positions)
+            .default(selection)
+            .interact_on(&term)?;
+        
+        crate::config::account::move_account(&account.id, position)?;
+        
+        println!();
+        println!("{}", style(format!("[SUCCESS] {} moved to position {}", account.name(), position + 1)).green());
+        println!();
+        println!("{}", style("Press any key to continue...").dim());
+        term.read_key()?;
+    }
+    
+    Ok(())
+}
+
+async fn reauthorize_account(accounts: &[crate::config::account::Account]) -> Result<()> {
+    let term = Term::stdout();
+    
+    // Accounts whose refresh token was rejected come first
+    let default = accounts.iter().position(|a| a.needs_reauth.is_some()).unwrap_or(0);
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select account to re-authorize")
+        .items(&account_choices(accounts))
+        .default(default)
+        .interact_on(&term)?;
+    
+    if let Some(account) = accounts.get(selection) {
+        term.clear_screen()?;
+        println!();
+        match crate::oauth::reauthorize_account(account).await {
+            Ok(account) => println!("{}", style(format!("[SUCCESS] {} re-authorized", account.name())).green()),
+            Err(e) => println!("{}", style(format!("Error: {}", e)).red()),
+        }
+        println!();
+        println!("{}", style("Press any key to continue...").dim());
+        term.read_key()?;
+    }
+    
+    Ok(())
+}
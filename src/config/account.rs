@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 use chrono::Utc;
 
@@ -13,6 +13,80 @@ pub struct Account {
     pub disabled: bool,
     pub created_at: i64,
     pub updated_at: i64,
+    /// Cached `cloudaicompanionProject` returned by loadCodeAssist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    /// Unix timestamp of when `project_id` was resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id_resolved_at: Option<i64>,
+    /// User-pinned project ID, used instead of calling loadCodeAssist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id_override: Option<String>,
+    /// User-chosen name shown instead of the email
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Position in the rotation, lower first; ties keep creation order
+    #[serde(default)]
+    pub sort_order: i64,
+    /// Set when Google rejected the refresh token; skipped until re-authorised
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub needs_reauth: Option<ReauthRequired>,
+    /// Tier for the `priority` strategy: lower tiers are used until exhausted
+    #[serde(default)]
+    pub priority: i64,
+    /// Share of requests for the `weighted` strategy (0 = only when nothing else is left)
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Groups used by `model_routes`, e.g. `claude-capable`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Send models matching `model` (supports one `*` wildcard) only to accounts in `group`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRoute {
+    pub model: String,
+    pub group: String,
+}
+
+/// How the pool picks an account for requests that are not pinned to one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    /// Stay on the current account, move to the next one on failure
+    #[default]
+    RoundRobin,
+    /// Lowest `priority` tier first, round robin within a tier
+    Priority,
+    /// Spread requests proportionally to `weight`
+    Weighted,
+    /// Account that has been idle the longest
+    LeastRecentlyUsed,
+    /// Account with the fewest failed attempts since the proxy started
+    FewestErrors,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReauthRequired {
+    pub reason: String,
+    /// Unix timestamp of the failed refresh
+    pub since: i64,
+}
+
+impl Account {
+    /// Label if set, otherwise the email
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.email)
+    }
+
+    /// Neither parked by the user nor waiting for re-authorisation
+    pub fn is_available(&self) -> bool {
+        !self.disabled && self.needs_reauth.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,7 +121,10 @@ pub fn get_accounts_dir() -> Result<PathBuf> {
 }
 
 pub fn list_accounts() -> Result<Vec<Account>> {
-    let accounts_dir = get_accounts_dir()?;
+    list_accounts_in(&get_accounts_dir()?)
+}
+
+pub fn list_accounts_in(accounts_dir: &Path) -> Result<Vec<Account>> {
     let mut accounts = Vec::new();
     
     for entry in std::fs::read_dir(accounts_dir)? {
@@ -61,22 +138,41 @@ pub fn list_accounts() -> Result<Vec<Account>> {
         }
     }
     
-    // Sort by created_at
-    accounts.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    // Rotation order, then creation time
+    accounts.sort_by_key(|a| (a.sort_order, a.created_at));
     
     Ok(accounts)
 }
 
+/// Write the account file atomically (temp file + rename) so a crash or a
+/// concurrent reader never observes a half-written token
 pub fn save_account(account: &Account) -> Result<()> {
-    let accounts_dir = get_accounts_dir()?;
+    save_account_in(&get_accounts_dir()?, account)
+}
+
+pub fn save_account_in(accounts_dir: &Path, account: &Account) -> Result<()> {
     let account_path = accounts_dir.join(format!("{}.json", account.id));
+    let tmp_path = accounts_dir.join(format!(".{}.json.tmp", account.id));
     let content = serde_json::to_string_pretty(account)?;
-    std::fs::write(&account_path, content)?;
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, &account_path)?;
     Ok(())
 }
 
+/// Current on-disk state of one account, `None` if its file was deleted
+pub fn load_account_in(accounts_dir: &Path, account_id: &str) -> Result<Option<Account>> {
+    let path = accounts_dir.join(format!("{}.json", account_id));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
 pub fn create_account(email: String, display_name: Option<String>, token: TokenData) -> Result<Account> {
     let now = Utc::now().timestamp();
+    // New accounts go to the end of the rotation
+    let sort_order = list_accounts()?.iter().map(|a| a.sort_order + 1).max().unwrap_or(0);
     let account = Account {
         id: Uuid::new_v4().to_string(),
         email,
@@ -85,6 +181,15 @@ pub fn create_account(email: String, display_name: Option<String>, token: TokenD
         disabled: false,
         created_at: now,
         updated_at: now,
+        project_id: None,
+        project_id_resolved_at: None,
+        project_id_override: None,
+        label: None,
+        sort_order,
+        needs_reauth: None,
+        priority: 0,
+        weight: default_weight(),
+        groups: Vec::new(),
     };
     
     save_account(&account)?;
@@ -101,3 +206,117 @@ pub fn delete_account(account_id: &str) -> Result<()> {
     
     Ok(())
 }
+
+/// Find an account by 1-based position (as listed), id, email or label
+pub fn find_account(query: &str) -> Result<Account> {
+    let accounts = list_accounts()?;
+    let query = query.trim();
+    
+    if let Ok(position) = query.parse::<usize>() {
+        if let Some(account) = position.checked_sub(1).and_then(|i| accounts.get(i)) {
+            return Ok(account.clone());
+        }
+    }
+    
+    accounts
+        .into_iter()
+        .find(|a| a.id == query || a.email.eq_ignore_ascii_case(query) || a.label.as_deref() == Some(query))
+        .ok_or_else(|| anyhow::anyhow!("No account matches '{}'", query))
+}
+
+/// Park or un-park an account; its refresh token is kept
+pub fn set_disabled(account_id: &str, disabled: bool) -> Result<Account> {
+    update_account(account_id, |account| account.disabled = disabled)
+}
+
+/// Store a fresh token after re-authorisation, keeping id, label and settings
+pub fn reauthorize(account_id: &str, token: TokenData, display_name: Option<String>) -> Result<Account> {
+    update_account(account_id, |account| {
+        account.token = token;
+        account.needs_reauth = None;
+        if display_name.is_some() {
+            account.display_name = display_name;
+        }
+    })
+}
+
+/// Set the tier used by the `priority` selection strategy
+pub fn set_priority(account_id: &str, priority: i64) -> Result<Account> {
+    update_account(account_id, |account| account.priority = priority)
+}
+
+/// Set the share used by the `weighted` selection strategy
+pub fn set_weight(account_id: &str, weight: u32) -> Result<Account> {
+    update_account(account_id, |account| account.weight = weight)
+}
+
+/// Replace the account's groups (empty clears them)
+pub fn set_groups(account_id: &str, groups: Vec<String>) -> Result<Account> {
+    let groups = normalize_groups(groups);
+    update_account(account_id, |account| account.groups = groups)
+}
+
+/// Trim, strip the `group:` prefix and drop blanks and duplicates, keeping the first occurrence
+fn normalize_groups(groups: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    groups
+        .iter()
+        .map(|g| g.trim().trim_start_matches("group:").trim().to_string())
+        .filter(|g| !g.is_empty() && seen.insert(g.clone()))
+        .collect()
+}
+
+/// Set or clear (`None` / blank) the account label
+pub fn set_label(account_id: &str, label: Option<String>) -> Result<Account> {
+    let label = label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    update_account(account_id, |account| account.label = label)
+}
+
+/// Move an account to `position` (0-based) in the rotation order
+pub fn move_account(account_id: &str, position: usize) -> Result<()> {
+    let mut accounts = list_accounts()?;
+    let from = accounts
+        .iter()
+        .position(|a| a.id == account_id)
+        .ok_or_else(|| anyhow::anyhow!("Account {} not found", account_id))?;
+    
+    let account = accounts.remove(from);
+    accounts.insert(position.min(accounts.len()), account);
+    
+    for (i, account) in accounts.iter_mut().enumerate() {
+        if account.sort_order != i as i64 {
+            account.sort_order = i as i64;
+            save_account(account)?;
+        }
+    }
+    Ok(())
+}
+
+fn update_account(account_id: &str, f: impl FnOnce(&mut Account)) -> Result<Account> {
+    let mut account = list_accounts()?
+        .into_iter()
+        .find(|a| a.id == account_id)
+        .ok_or_else(|| anyhow::anyhow!("Account {} not found", account_id))?;
+    f(&mut account);
+    account.updated_at = Utc::now().timestamp();
+    save_account(&account)?;
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_groups() {
+        let groups = vec![
+            " pro ".to_string(),
+            "group:flash".to_string(),
+            "".to_string(),
+            "pro".to_string(),
+            "group: flash".to_string(),
+            "free".to_string(),
+        ];
+        assert_eq!(normalize_groups(groups), vec!["pro", "flash", "free"]);
+    }
+}
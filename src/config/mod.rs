@@ -3,6 +3,7 @@ pub mod proxy;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +17,17 @@ pub struct ProxyConfig {
     pub api_key: String,
     pub auto_start: bool,
     pub allow_lan_access: bool,
+    /// Models tried in order once every account failed for the key model,
+    /// e.g. `"gemini-3-pro-high": ["gemini-3-pro-low", "gemini-2.5-pro"]`
+    #[serde(default)]
+    pub model_fallbacks: HashMap<String, Vec<String>>,
+    /// round_robin (default), priority, weighted, least_recently_used or fewest_errors
+    #[serde(default)]
+    pub selection_strategy: account::SelectionStrategy,
+    /// Model pattern -> account group rules, first match wins,
+    /// e.g. `{"model": "claude-*", "group": "claude-capable"}`
+    #[serde(default)]
+    pub model_routes: Vec<account::ModelRoute>,
 }
 
 impl Default for Config {
@@ -26,6 +38,9 @@ impl Default for Config {
                 api_key: generate_api_key(),
                 auto_start: true,
                 allow_lan_access: true,
+                model_fallbacks: HashMap::new(),
+                selection_strategy: account::SelectionStrategy::default(),
+                model_routes: Vec::new(),
             },
         }
     }
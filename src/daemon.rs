@@ -17,6 +17,9 @@ pub async fn start_foreground() -> Result<()> {
         port: config.proxy.port,
         api_key: config.proxy.api_key.clone(),
         allow_lan_access: config.proxy.allow_lan_access,
+        model_fallbacks: config.proxy.model_fallbacks.clone(),
+        selection_strategy: config.proxy.selection_strategy,
+        model_routes: config.proxy.model_routes.clone(),
     };
     crate::proxy::start_server(proxy_config).await?;
     
@@ -106,13 +109,75 @@ pub async fn status() -> Result<()> {
         println!("PID: {}", pid.trim());
         println!("Port: {}", config.proxy.port);
         println!("Address: http://127.0.0.1:{}", config.proxy.port);
+        
+        match fetch_daemon_status(config.proxy.port, &config.proxy.api_key).await {
+            Ok(daemon_status) => print_cooldowns(&daemon_status),
+            Err(e) => println!("Cooldowns: unavailable ({})", e),
+        }
     } else {
         println!("Status: Stopped");
     }
     
+    print_needs_reauth()?;
+    
+    Ok(())
+}
+
+/// Accounts excluded from the pool because Google rejected their refresh token
+fn print_needs_reauth() -> Result<()> {
+    let accounts = crate::config::account::list_accounts()?;
+    let revoked: Vec<_> = accounts.iter().filter(|a| a.needs_reauth.is_some()).collect();
+    if revoked.is_empty() {
+        return Ok(());
+    }
+    
+    println!();
+    println!("{}", console::style(format!("⚠️  {} account(s) need re-authorization:", revoked.len())).red().bold());
+    for account in revoked {
+        if let Some(reauth) = &account.needs_reauth {
+            let since = chrono::DateTime::from_timestamp(reauth.since, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default();
+            println!("   {} - {} (since {})", account.name(), reauth.reason, since);
+        }
+    }
+    println!("   Run 'drovity accounts reauth <account>' to sign in again");
     Ok(())
 }
 
+/// Ask the running daemon for its live state (GET /status)
+async fn fetch_daemon_status(port: u16, api_key: &str) -> Result<serde_json::Value> {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(3))
+        .build()?;
+    let response = client
+        .get(format!("http://127.0.0.1:{}/status", port))
+        .bearer_auth(api_key)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+fn print_cooldowns(daemon_status: &serde_json::Value) {
+    let cooldowns = daemon_status["cooldowns"].as_array().cloned().unwrap_or_default();
+    if cooldowns.is_empty() {
+        println!("Cooldowns: none");
+        return;
+    }
+    
+    println!("Cooldowns:");
+    for cooldown in cooldowns {
+        println!(
+            "   {} [{}] - {}s remaining ({})",
+            cooldown["email"].as_str().unwrap_or("unknown"),
+            cooldown["model"].as_str().unwrap_or("all models"),
+            cooldown["remaining_secs"].as_u64().unwrap_or(0),
+            cooldown["reason"].as_str().unwrap_or("")
+        );
+    }
+}
+
 pub async fn is_running() -> Result<bool> {
     let pid_file = get_pid_file()?;
     
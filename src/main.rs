@@ -37,6 +37,11 @@ enum Commands {
     Status,
     /// Run proxy in background (daemon mode)
     Hide,
+    /// Manage accounts (list, enable, disable, label, move, priority, weight, groups, reauth)
+    Accounts {
+        #[command(subcommand)]
+        command: Option<cli::accounts::AccountCommand>,
+    },
 }
 
 #[tokio::main]
@@ -54,6 +59,9 @@ async fn main() -> Result<()> {
             port: config.proxy.port,
             api_key: config.proxy.api_key.clone(),
             allow_lan_access: config.proxy.allow_lan_access,
+            model_fallbacks: config.proxy.model_fallbacks.clone(),
+            selection_strategy: config.proxy.selection_strategy,
+            model_routes: config.proxy.model_routes.clone(),
         };
         crate::proxy::start_server(proxy_config).await?;
         return Ok(());
@@ -85,6 +93,10 @@ async fn main() -> Result<()> {
             // Start in background
             daemon::start_background(cli.log).await?;
         }
+        Some(Commands::Accounts { command }) => {
+            // Account changes are picked up by a running proxy automatically
+            cli::accounts::run_account_command(command.unwrap_or(cli::accounts::AccountCommand::List)).await?;
+        }
     }
 
     Ok(())
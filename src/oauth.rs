@@ -19,6 +19,43 @@ pub struct TokenResponse {
     pub refresh_token: Option<String>,
 }
 
+/// Error returned by the token endpoint,
+/// e.g. `{"error": "invalid_grant", "error_description": "Token has been expired or revoked."}`
+#[derive(Debug, thiserror::Error)]
+#[error("Token refresh failed: {body}")]
+pub struct OAuthError {
+    pub status: u16,
+    pub error: Option<String>,
+    pub description: Option<String>,
+    pub body: String,
+}
+
+impl OAuthError {
+    pub fn from_response(status: u16, body: &str) -> Self {
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        Self {
+            status,
+            error: parsed["error"].as_str().map(str::to_string),
+            description: parsed["error_description"].as_str().map(str::to_string),
+            body: body.to_string(),
+        }
+    }
+
+    /// Refresh token revoked or expired: retrying can never succeed
+    pub fn is_permanent(&self) -> bool {
+        self.error.as_deref() == Some("invalid_grant")
+    }
+
+    /// Short human-readable reason
+    pub fn reason(&self) -> String {
+        match (&self.error, &self.description) {
+            (Some(error), Some(description)) => format!("{}: {}", error, description),
+            (Some(error), None) => error.clone(),
+            _ => format!("HTTP {}", self.status),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
     pub email: String,
@@ -46,6 +83,36 @@ impl UserInfo {
 
 /// Generate OAuth authorization URL and get authorization code manually
 pub async fn authorize_with_manual_callback() -> Result<crate::config::account::Account> {
+    let code = prompt_for_code()?;
+    let (token, user_info) = exchange_code_for_tokens(&code).await?;
+
+    // Create and save account
+    crate::config::account::create_account(
+        user_info.email.clone(),
+        user_info.get_display_name(),
+        token,
+    )
+}
+
+/// Re-run the authorisation for an existing account, keeping its id and settings
+pub async fn reauthorize_account(account: &crate::config::account::Account) -> Result<crate::config::account::Account> {
+    println!("🔁 Re-authorizing {}", console::style(&account.email).cyan().bold());
+    let code = prompt_for_code()?;
+    let (token, user_info) = exchange_code_for_tokens(&code).await?;
+
+    if !user_info.email.eq_ignore_ascii_case(&account.email) {
+        anyhow::bail!(
+            "Authorized as {} but this account is {}; use 'Add New Account' for a different account",
+            user_info.email,
+            account.email
+        );
+    }
+
+    crate::config::account::reauthorize(&account.id, token, user_info.get_display_name())
+}
+
+/// Show the authorization URL and read back the code (or callback URL)
+fn prompt_for_code() -> Result<String> {
     // Step 1: Generate authorization URL
     let auth_url = generate_auth_url()?;
     
@@ -80,13 +147,7 @@ pub async fn authorize_with_manual_callback() -> Result<crate::config::account::
     }
     
     // Extract code from URL if user pasted full URL
-    let code = extract_code_from_input(&input)?;
-    
-    println!();
-    println!("{}", console::style("⏳ Exchanging code for tokens...").yellow());
-    
-    // Step 3: Exchange code for tokens
-    exchange_code_for_tokens(&code).await
+    extract_code_from_input(&input)
 }
 /// Extract authorization code from user input
 /// Accepts either:
@@ -144,7 +205,10 @@ fn generate_auth_url() -> Result<String> {
 }
 
 /// Exchange authorization code for tokens
-async fn exchange_code_for_tokens(code: &str) -> Result<crate::config::account::Account> {
+async fn exchange_code_for_tokens(code: &str) -> Result<(crate::config::account::TokenData, UserInfo)> {
+    println!();
+    println!("{}", console::style("⏳ Exchanging code for tokens...").yellow());
+    
     let client = reqwest::Client::new();
     
     let params = [
@@ -192,14 +256,7 @@ async fn exchange_code_for_tokens(code: &str) -> Result<crate::config::account::
         token_res.expires_in,
     );
 
-    // Create and save account
-    let account = crate::config::account::create_account(
-        user_info.email.clone(),
-        user_info.get_display_name(),
-        token,
-    )?;
-
-    Ok(account)
+    Ok((token, user_info))
 }
 
 /// Get user info from access token
@@ -244,9 +301,10 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse>
         .await
         .context("Failed to refresh token")?;
 
-    if !response.status().is_success() {
+    let status = response.status();
+    if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Token refresh failed: {}", error_text);
+        return Err(OAuthError::from_response(status.as_u16(), &error_text).into());
     }
 
     let token_data: TokenResponse = response
@@ -256,3 +314,22 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse>
 
     Ok(token_data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oauth_error_classification() {
+        let revoked = OAuthError::from_response(
+            400,
+            r#"{"error": "invalid_grant", "error_description": "Token has been expired or revoked."}"#,
+        );
+        assert!(revoked.is_permanent());
+        assert_eq!(revoked.reason(), "invalid_grant: Token has been expired or revoked.");
+
+        let transient = OAuthError::from_response(503, "Service Unavailable");
+        assert!(!transient.is_permanent());
+        assert_eq!(transient.reason(), "HTTP 503");
+    }
+}
@@ -0,0 +1,839 @@
+// Account pool
+//
+// Holds the live account list and handles selection (session affinity + rotation),
+// health (cooldowns) and access token / project_id refresh. Hot-reloads when
+// ~/.drovity/accounts changes (polled) or on SIGHUP; in-flight streams keep their own
+// token and are not affected.
+
+use anyhow::Result;
+use chrono::Utc;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Mutex;
+
+use super::cooldown::CooldownTable;
+use super::session_affinity::SessionAffinity;
+use crate::config::account::{Account, ModelRoute, ReauthRequired, SelectionStrategy, TokenData};
+
+/// How often the accounts directory is checked for changes
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Refresh access tokens that expire within this many seconds
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Usage of one account since the proxy started, for the selection strategies
+#[derive(Default)]
+struct AccountStats {
+    last_used: Option<Instant>,
+    errors: u32,
+    /// Smooth weighted round robin state
+    current_weight: i64,
+}
+
+pub struct AccountPool {
+    /// Copy-on-write snapshot: readers clone the `Arc`, never the accounts
+    accounts: RwLock<Arc<Vec<Account>>>,
+    current_index: StdMutex<usize>,
+    strategy: SelectionStrategy,
+    /// Model pattern -> account group, first match wins
+    routes: Vec<ModelRoute>,
+    stats: StdMutex<HashMap<String, AccountStats>>,
+    /// Per-account refresh locks so concurrent requests share one in-flight refresh
+    refresh_locks: DashMap<String, Arc<Mutex<()>>>,
+    /// Per-account locks around the account file's read-modify-write in `update`
+    file_locks: DashMap<String, Arc<StdMutex<()>>>,
+    /// Conversation -> account stickiness
+    affinity: SessionAffinity,
+    /// Rate-limited accounts, skipped by every request until they recover
+    cooldowns: CooldownTable,
+    /// Where accounts are persisted; `None` keeps them in memory only
+    accounts_dir: Option<PathBuf>,
+}
+
+impl AccountPool {
+    pub fn new(accounts: Vec<Account>) -> Self {
+        Self {
+            accounts: RwLock::new(Arc::new(accounts)),
+            current_index: StdMutex::new(0),
+            strategy: SelectionStrategy::default(),
+            routes: Vec::new(),
+            stats: StdMutex::new(HashMap::new()),
+            refresh_locks: DashMap::new(),
+            file_locks: DashMap::new(),
+            affinity: SessionAffinity::new(),
+            cooldowns: CooldownTable::new(),
+            accounts_dir: None,
+        }
+    }
+
+    /// Persist account changes to (and reload from) `dir`
+    pub fn with_accounts_dir(mut self, dir: PathBuf) -> Self {
+        self.accounts_dir = Some(dir);
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_routes(mut self, routes: Vec<ModelRoute>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Load the pool from ~/.drovity/accounts
+    pub fn load() -> Result<Self> {
+        let dir = crate::config::account::get_accounts_dir()?;
+        let accounts = crate::config::account::list_accounts_in(&dir)?;
+        Ok(Self::new(accounts).with_accounts_dir(dir))
+    }
+
+    /// Current accounts, cheap to take per request
+    pub fn snapshot(&self) -> Arc<Vec<Account>> {
+        self.accounts.read().map(|a| a.clone()).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Latest in-memory state of an account
+    pub fn get(&self, account_id: &str) -> Option<Account> {
+        self.snapshot().iter().find(|a| a.id == account_id).cloned()
+    }
+
+    pub fn cooldowns(&self) -> &CooldownTable {
+        &self.cooldowns
+    }
+
+    /// Account group the model is routed to, if any rule matches
+    fn route_group(&self, model: &str) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|route| super::common::model_mapping::wildcard_match(&route.model, model))
+            .map(|route| route.group.trim_start_matches("group:"))
+    }
+
+    /// Enabled and allowed to serve the model by the routing rules
+    fn serves(&self, account: &Account, model: &str) -> bool {
+        account.is_available()
+            && self
+                .route_group(model)
+                .is_none_or(|group| account.groups.iter().any(|g| g == group))
+    }
+
+    /// Accounts that may serve requests
+    fn is_usable(&self, account: &Account, model: &str) -> bool {
+        self.serves(account, model) && !self.cooldowns.is_cooling(&account.id, model)
+    }
+
+    /// Pick the account for one attempt.
+    ///
+    /// A conversation sticks to the account it was bound to; only once that account
+    /// has failed in this request do we fall back to the global rotation, and the
+    /// session is re-bound to whichever account is chosen.
+    pub fn select(
+        &self,
+        session_key: &str,
+        failed_emails: &mut HashSet<String>,
+        force_rotate: bool,
+        model: &str,
+    ) -> Option<Account> {
+        let accounts = self.snapshot();
+
+        if !accounts.iter().any(|a| self.serves(a, model)) {
+            tracing::warn!(
+                "⚠️ No enabled account may serve {} (routed to group {:?})",
+                model,
+                self.route_group(model)
+            );
+            return None;
+        }
+
+        let bound_id = self.affinity.get(session_key);
+        if let Some(account) = bound_id
+            .as_ref()
+            .and_then(|id| accounts.iter().find(|a| &a.id == id))
+            .filter(|a| !failed_emails.contains(&a.email) && self.is_usable(a, model))
+        {
+            tracing::info!("   🔗 Session {} → {} (sticky)", session_key, account.email);
+            self.mark_used(&account.id);
+            return Some(account.clone());
+        }
+
+        let account = {
+            let mut index_guard = self.current_index.lock().ok()?;
+            let start_index = *index_guard;
+
+            let candidates = self.candidates(&accounts, start_index, failed_emails, model);
+
+            match self.pick(&accounts, &candidates, true) {
+                Some(idx) => {
+                    // If we had to search or force_rotate is true, update global index
+                    if idx != start_index || force_rotate {
+                        *index_guard = idx;
+                    }
+                    Some(accounts[idx].clone())
+                }
+                // If no fresh account found (all failed or cooling), fall back to the one recovering first
+                None => {
+                    tracing::warn!("⚠️ All accounts failed or cooling down. Resetting local blacklist for this request.");
+                    failed_emails.clear();
+                    self.recovering_first(&accounts, *index_guard, model).cloned()
+                }
+            }
+        }?;
+
+        self.mark_used(&account.id);
+        let reason = if bound_id.is_some() { "failover" } else { "new" };
+        tracing::info!("   🔗 Session {} → {} ({})", session_key, account.email, reason);
+        self.affinity.bind(session_key, &account.id);
+        Some(account)
+    }
+
+    /// The account `select` would pick for a first attempt, without recording
+    /// anything: no session binding, usage stamp or weighted round-robin step.
+    /// For requests that only inspect (count_tokens) and must not shift rotation.
+    pub fn peek(&self, session_key: &str, model: &str) -> Option<Account> {
+        let accounts = self.snapshot();
+
+        if let Some(account) = self
+            .affinity
+            .peek(session_key)
+            .and_then(|id| accounts.iter().find(|a| a.id == id))
+            .filter(|a| self.is_usable(a, model))
+        {
+            return Some(account.clone());
+        }
+
+        let start_index = self.current_index.lock().map(|i| *i).unwrap_or(0);
+        let candidates = self.candidates(&accounts, start_index, &HashSet::new(), model);
+        match self.pick(&accounts, &candidates, false) {
+            Some(idx) => Some(accounts[idx].clone()),
+            None => self.recovering_first(&accounts, start_index, model).cloned(),
+        }
+    }
+
+    /// Accounts that may take an attempt, in rotation order from `start_index`
+    fn candidates(
+        &self,
+        accounts: &[Account],
+        start_index: usize,
+        failed_emails: &HashSet<String>,
+        model: &str,
+    ) -> Vec<usize> {
+        let pool_size = accounts.len();
+        (0..pool_size)
+            .map(|i| (start_index + i) % pool_size)
+            .filter(|&idx| {
+                let acc = &accounts[idx];
+                // Accounts cooling down after a rate limit are skipped by every request
+                !failed_emails.contains(&acc.email) && self.is_usable(acc, model)
+            })
+            .collect()
+    }
+
+    /// No fresh candidate: the current account if usable, else the one recovering first
+    fn recovering_first<'a>(&self, accounts: &'a [Account], index: usize, model: &str) -> Option<&'a Account> {
+        accounts
+            .get(index)
+            .filter(|a| self.is_usable(a, model))
+            .or_else(|| {
+                accounts
+                    .iter()
+                    .filter(|a| self.serves(a, model))
+                    .min_by_key(|a| self.cooldowns.remaining(&a.id, model).unwrap_or_default())
+            })
+    }
+
+    /// Choose among `candidates` (indexes into `accounts`, in rotation order).
+    /// `record` advances the weighted round-robin state; `peek` leaves it alone.
+    fn pick(&self, accounts: &[Account], candidates: &[usize], record: bool) -> Option<usize> {
+        let mut stats = self.stats.lock().ok()?;
+        let last_used = |stats: &HashMap<String, AccountStats>, idx: usize| {
+            stats.get(&accounts[idx].id).and_then(|s| s.last_used)
+        };
+
+        match self.strategy {
+            SelectionStrategy::RoundRobin => candidates.first().copied(),
+            SelectionStrategy::Priority => {
+                let tier = candidates.iter().map(|&idx| accounts[idx].priority).min()?;
+                candidates.iter().copied().find(|&idx| accounts[idx].priority == tier)
+            }
+            SelectionStrategy::LeastRecentlyUsed => candidates
+                .iter()
+                .copied()
+                .min_by_key(|&idx| last_used(&stats, idx)),
+            SelectionStrategy::FewestErrors => candidates.iter().copied().min_by_key(|&idx| {
+                let errors = stats.get(&accounts[idx].id).map_or(0, |s| s.errors);
+                (errors, last_used(&stats, idx))
+            }),
+            SelectionStrategy::Weighted => {
+                // Smooth weighted round robin: deterministic and evenly interleaved
+                let weighted: Vec<usize> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|&idx| accounts[idx].weight > 0)
+                    .collect();
+                if weighted.is_empty() {
+                    return candidates.first().copied();
+                }
+
+                let total: i64 = weighted.iter().map(|&idx| accounts[idx].weight as i64).sum();
+                let mut best: Option<(usize, i64)> = None;
+                for &idx in &weighted {
+                    let current = stats.get(&accounts[idx].id).map_or(0, |s| s.current_weight)
+                        + accounts[idx].weight as i64;
+                    if best.is_none_or(|(_, weight)| current > weight) {
+                        best = Some((idx, current));
+                    }
+                }
+                let (idx, _) = best?;
+                if record {
+                    for &candidate in &weighted {
+                        stats.entry(accounts[candidate].id.clone()).or_default().current_weight +=
+                            accounts[candidate].weight as i64;
+                    }
+                    stats.entry(accounts[idx].id.clone()).or_default().current_weight -= total;
+                }
+                Some(idx)
+            }
+        }
+    }
+
+    fn mark_used(&self, account_id: &str) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.entry(account_id.to_string()).or_default().last_used = Some(Instant::now());
+        }
+    }
+
+    /// Count a failed attempt against the account (used by `fewest_errors`)
+    pub fn record_error(&self, account_id: &str) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.entry(account_id.to_string()).or_default().errors += 1;
+        }
+    }
+
+    /// Apply `f` to the account file as it is on disk now, persist it and
+    /// adopt the result in the pool.
+    ///
+    /// `f` must only change the fields the daemon owns (token, project cache,
+    /// re-auth state): starting from the file keeps edits made by
+    /// `drovity accounts ...` since the last reload. Accounts whose file was
+    /// deleted are not written back, so a late refresh never resurrects them.
+    fn update(&self, account_id: &str, f: impl FnOnce(&mut Account) -> bool) -> Option<Account> {
+        use crate::config::account::{load_account_in, save_account_in};
+
+        // Serialises read-modify-write per file; the pool lock is only taken for the swap,
+        // so selection on other workers never waits for disk I/O
+        let file_lock = self
+            .file_locks
+            .entry(account_id.to_string())
+            .or_insert_with(|| Arc::new(StdMutex::new(())))
+            .clone();
+        let _file_guard = file_lock.lock().ok()?;
+
+        let live = self.get(account_id)?;
+        let (mut account, persist) = match &self.accounts_dir {
+            Some(dir) => match load_account_in(dir, account_id) {
+                Ok(Some(on_disk)) => (on_disk, true),
+                Ok(None) => return None,
+                Err(e) => {
+                    // Don't overwrite a file we cannot read; the next reload sorts it out
+                    tracing::warn!("⚠️ Failed to read account {}: {}", account_id, e);
+                    (live, false)
+                }
+            },
+            None => (live, false),
+        };
+
+        if !f(&mut account) {
+            return None;
+        }
+
+        if let Some(dir) = self.accounts_dir.as_ref().filter(|_| persist) {
+            if let Err(e) = save_account_in(dir, &account) {
+                // The in-memory pool is still updated, so requests keep working until restart
+                tracing::warn!("⚠️ Failed to persist account {}: {}", account.email, e);
+            }
+        }
+
+        // Removed by a reload meanwhile: nothing to swap
+        let mut guard = self.accounts.write().ok()?;
+        let index = guard.iter().position(|a| a.id == account_id)?;
+        Arc::make_mut(&mut guard)[index] = account.clone();
+        Some(account)
+    }
+
+    /// Return a valid access token for the account, refreshing it if it expires soon.
+    ///
+    /// The refreshed token is written back to the pool and to
+    /// `~/.drovity/accounts/<id>.json`. Concurrent callers for the same account
+    /// wait on a per-account lock and reuse the token refreshed by the first one.
+    pub async fn access_token(&self, account: &Account) -> Result<String> {
+        let needs_refresh =
+            |token: &TokenData| token.expiry_timestamp < Utc::now().timestamp() + TOKEN_REFRESH_MARGIN_SECS;
+
+        if !needs_refresh(&account.token) {
+            return Ok(account.token.access_token.clone());
+        }
+
+        let lock = self
+            .refresh_locks
+            .entry(account.id.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone();
+        let _guard = lock.lock().await;
+
+        // Another request may have refreshed this account while we were waiting
+        let current = self.get(&account.id).unwrap_or_else(|| account.clone());
+        if !needs_refresh(&current.token) {
+            tracing::debug!("   Reusing token refreshed by a concurrent request for {}", current.email);
+            return Ok(current.token.access_token);
+        }
+
+        tracing::info!("🔄 Refreshing access token for {}", current.email);
+        let token_response = match crate::oauth::refresh_access_token(&current.token.refresh_token).await {
+            Ok(response) => response,
+            Err(e) => {
+                if let Some(oauth) = e.downcast_ref::<crate::oauth::OAuthError>().filter(|o| o.is_permanent()) {
+                    self.mark_needs_reauth(&current.id, &oauth.reason());
+                }
+                return Err(e);
+            }
+        };
+
+        let used_refresh_token = current.token.refresh_token;
+        let token = TokenData::new(
+            token_response.access_token,
+            token_response.refresh_token.unwrap_or_else(|| used_refresh_token.clone()),
+            token_response.expires_in,
+        );
+        let access_token = token.access_token.clone();
+        self.update(&account.id, |slot| {
+            // Re-authorised meanwhile: the file's new token wins
+            if slot.token.refresh_token != used_refresh_token {
+                return false;
+            }
+            slot.token = token;
+            slot.updated_at = Utc::now().timestamp();
+            true
+        });
+
+        Ok(access_token)
+    }
+
+    /// Take an account with a revoked refresh token out of the pool until it is re-authorised
+    fn mark_needs_reauth(&self, account_id: &str, reason: &str) {
+        let marked = self.update(account_id, |slot| {
+            slot.needs_reauth = Some(ReauthRequired {
+                reason: reason.to_string(),
+                since: Utc::now().timestamp(),
+            });
+            true
+        });
+        if let Some(account) = marked {
+            tracing::error!(
+                "❌ Refresh token for {} was rejected ({}), excluding it until re-authorised ('drovity menu' → Accounts)",
+                account.email,
+                reason
+            );
+        }
+    }
+
+    /// Return the project_id for an account, calling loadCodeAssist only when the
+    /// cached value is missing or older than `PROJECT_ID_TTL_SECS`.
+    /// A per-account `project_id_override` is used as-is.
+    pub async fn project_id(&self, account: &Account, token: &str) -> String {
+        use super::project_resolver::{cached_project_id, fetch_project_id, generate_mock_project_id};
+
+        let now = Utc::now().timestamp();
+        let current = self.get(&account.id).unwrap_or_else(|| account.clone());
+
+        if let Some(pid) = cached_project_id(&current, now) {
+            return pid;
+        }
+
+        let pid = match fetch_project_id(token).await {
+            Ok(pid) => pid,
+            Err(e) => {
+                // Don't cache the fallback, so the next request tries loadCodeAssist again
+                tracing::warn!("   Failed to get project_id, using mock: {}", e);
+                return generate_mock_project_id();
+            }
+        };
+
+        self.update(&account.id, |slot| {
+            slot.project_id = Some(pid.clone());
+            slot.project_id_resolved_at = Some(now);
+            true
+        });
+
+        pid
+    }
+
+    /// Drop the cached project_id so the next request re-resolves it
+    pub fn invalidate_project_id(&self, account_id: &str) {
+        self.update(account_id, |slot| {
+            if slot.project_id_resolved_at.is_none() || slot.project_id_override.is_some() {
+                return false;
+            }
+            tracing::info!("   Invalidating cached project_id for {}", slot.email);
+            slot.project_id_resolved_at = None;
+            true
+        });
+    }
+
+    /// Re-read ~/.drovity/accounts and apply adds, removals and `disabled` flips
+    pub fn reload(&self) -> Result<()> {
+        let dir = self
+            .accounts_dir
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("account pool is not backed by a directory"))?;
+        let on_disk = crate::config::account::list_accounts_in(dir)?;
+
+        let mut guard = self
+            .accounts
+            .write()
+            .map_err(|_| anyhow::anyhow!("account pool lock poisoned"))?;
+        let merged = merge_accounts(&guard, on_disk);
+
+        for account in merged.iter() {
+            match guard.iter().find(|a| a.id == account.id) {
+                None => tracing::info!("➕ Account added: {}", account.email),
+                Some(live) if live.needs_reauth.is_some() && account.needs_reauth.is_none() => {
+                    tracing::info!("   Account {} re-authorised", account.email)
+                }
+                Some(live) if live.disabled != account.disabled => tracing::info!(
+                    "   Account {} {}",
+                    account.email,
+                    if account.disabled { "disabled" } else { "enabled" }
+                ),
+                Some(_) => {}
+            }
+        }
+        for live in guard.iter().filter(|a| !merged.iter().any(|m| m.id == a.id)) {
+            tracing::info!("➖ Account removed: {}", live.email);
+        }
+
+        *guard = Arc::new(merged);
+        Ok(())
+    }
+
+    /// Reload when the accounts directory changes, or on SIGHUP (Unix)
+    pub fn spawn_watcher(self: &Arc<Self>) {
+        let Some(dir) = self.accounts_dir.clone() else {
+            tracing::warn!("⚠️ Account hot reload disabled: pool is not backed by a directory");
+            return;
+        };
+
+        let pool = self.clone();
+        tokio::spawn(async move {
+            let mut last = dir_fingerprint(&dir);
+            let mut interval = tokio::time::interval(RELOAD_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let current = dir_fingerprint(&dir);
+                if current != last {
+                    last = current;
+                    tracing::info!("🔄 Accounts directory changed, reloading");
+                    if let Err(e) = pool.reload() {
+                        tracing::warn!("⚠️ Failed to reload accounts: {}", e);
+                    }
+                }
+            }
+        });
+
+        #[cfg(unix)]
+        {
+            let pool = self.clone();
+            tokio::spawn(async move {
+                use tokio::signal::unix::{signal, SignalKind};
+                let mut hangup = match signal(SignalKind::hangup()) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::warn!("⚠️ Cannot listen for SIGHUP: {}", e);
+                        return;
+                    }
+                };
+                while hangup.recv().await.is_some() {
+                    tracing::info!("🔄 SIGHUP received, reloading accounts");
+                    if let Err(e) = pool.reload() {
+                        tracing::warn!("⚠️ Failed to reload accounts: {}", e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Accounts from disk, keeping tokens this daemon refreshed more recently than
+/// the file (e.g. when the menu rewrote an account from a stale copy)
+fn merge_accounts(live: &[Account], on_disk: Vec<Account>) -> Vec<Account> {
+    on_disk
+        .into_iter()
+        .map(|mut account| {
+            if let Some(current) = live.iter().find(|a| a.id == account.id) {
+                if current.token.expiry_timestamp > account.token.expiry_timestamp {
+                    account.token = current.token.clone();
+                }
+            }
+            account
+        })
+        .collect()
+}
+
+/// File names and modification times of the account files
+fn dir_fingerprint(dir: &Path) -> Vec<(String, Option<SystemTime>)> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().extension().and_then(|s| s.to_str()) == Some("json"))
+        .map(|e| {
+            let modified = e.metadata().and_then(|m| m.modified()).ok();
+            (e.file_name().to_string_lossy().into_owned(), modified)
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: &str, expires_in: i64) -> Account {
+        Account {
+            id: id.to_string(),
+            email: format!("{}@example.com", id),
+            display_name: None,
+            token: TokenData::new(format!("access-{}", expires_in), "refresh".to_string(), expires_in),
+            disabled: false,
+            created_at: 0,
+            updated_at: 0,
+            project_id: None,
+            project_id_resolved_at: None,
+            project_id_override: None,
+            label: None,
+            sort_order: 0,
+            needs_reauth: None,
+            priority: 0,
+            weight: 1,
+            groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_merge_accounts() {
+        let live = vec![account("a", 3600), account("b", 3600)];
+
+        let mut stale_a = account("a", 60);
+        stale_a.disabled = true;
+        let merged = merge_accounts(&live, vec![stale_a, account("c", 3600)]);
+
+        // b removed, c added, a disabled but keeps the fresher live token
+        let ids: Vec<_> = merged.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert!(merged[0].disabled);
+        assert_eq!(merged[0].token.access_token, "access-3600");
+    }
+
+    #[test]
+    fn test_select_skips_disabled_and_cooling() {
+        let mut disabled = account("a", 3600);
+        disabled.disabled = true;
+        let mut revoked = account("d", 3600);
+        revoked.needs_reauth = Some(ReauthRequired {
+            reason: "invalid_grant".to_string(),
+            since: 0,
+        });
+        let pool = AccountPool::new(vec![disabled, account("b", 3600), account("c", 3600), revoked]);
+        pool.cooldowns().set("b", "b@example.com", "gemini-2.5-pro", Duration::from_secs(60), "429");
+
+        let mut failed = HashSet::new();
+        let selected = pool.select("session", &mut failed, false, "gemini-2.5-pro").unwrap();
+        assert_eq!(selected.id, "c");
+
+        // Other models are not affected by the cooldown
+        failed.insert("c@example.com".to_string());
+        let selected = pool.select("other-session", &mut failed, true, "gemini-2.5-flash").unwrap();
+        assert_eq!(selected.id, "b");
+    }
+
+    /// Pick for a fresh conversation, so session affinity never kicks in
+    fn pick(pool: &AccountPool, failed: &[&str]) -> String {
+        static SESSION: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let session = format!("session-{}", SESSION.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
+        let mut failed: HashSet<String> = failed.iter().map(|id| format!("{}@example.com", id)).collect();
+        pool.select(&session, &mut failed, false, "gemini-2.5-pro").unwrap().id
+    }
+
+    fn pool_with(strategy: SelectionStrategy, setup: impl Fn(&mut Account)) -> AccountPool {
+        let accounts = ["a", "b", "c"]
+            .iter()
+            .map(|id| {
+                let mut acc = account(id, 3600);
+                setup(&mut acc);
+                acc
+            })
+            .collect();
+        AccountPool::new(accounts).with_strategy(strategy)
+    }
+
+    #[test]
+    fn test_round_robin_strategy() {
+        let pool = pool_with(SelectionStrategy::RoundRobin, |_| {});
+        assert_eq!(pick(&pool, &[]), "a");
+        assert_eq!(pick(&pool, &[]), "a");
+        // Moves on after a failure and stays there
+        assert_eq!(pick(&pool, &["a"]), "b");
+        assert_eq!(pick(&pool, &[]), "b");
+    }
+
+    #[test]
+    fn test_priority_strategy() {
+        let pool = pool_with(SelectionStrategy::Priority, |acc| {
+            // "a" is the personal account, only used once the work tier is exhausted
+            acc.priority = if acc.id == "a" { 1 } else { 0 };
+        });
+        assert_eq!(pick(&pool, &[]), "b");
+        assert_eq!(pick(&pool, &["b"]), "c");
+
+        for id in ["b", "c"] {
+            pool.cooldowns().set(id, "", "gemini-2.5-pro", Duration::from_secs(60), "429");
+        }
+        assert_eq!(pick(&pool, &[]), "a");
+    }
+
+    #[test]
+    fn test_weighted_strategy() {
+        let pool = pool_with(SelectionStrategy::Weighted, |acc| {
+            acc.weight = match acc.id.as_str() {
+                "a" => 3,
+                "b" => 1,
+                _ => 0,
+            };
+        });
+        let picks: Vec<String> = (0..8).map(|_| pick(&pool, &[])).collect();
+        assert_eq!(picks.iter().filter(|id| *id == "a").count(), 6);
+        assert_eq!(picks.iter().filter(|id| *id == "b").count(), 2);
+
+        // Weight 0 is only used when nothing else is left
+        assert_eq!(pick(&pool, &["a", "b"]), "c");
+    }
+
+    #[test]
+    fn test_peek_records_nothing() {
+        let pool = pool_with(SelectionStrategy::Weighted, |acc| {
+            acc.weight = if acc.id == "a" { 3 } else { 1 };
+        });
+        for _ in 0..3 {
+            assert_eq!(pool.peek("peek-session", "gemini-2.5-pro").unwrap().id, "a");
+        }
+        // Weighted counters did not move: the first real pick is still "a", then "b"
+        let picks: Vec<String> = (0..5).map(|_| pick(&pool, &[])).collect();
+        assert_eq!(picks, vec!["a", "b", "a", "c", "a"]);
+
+        // Nor is the session bound
+        assert!(pool.affinity.peek("peek-session").is_none());
+
+        let pool = pool_with(SelectionStrategy::LeastRecentlyUsed, |_| {});
+        assert_eq!(pool.peek("s", "gemini-2.5-pro").unwrap().id, "a");
+        assert_eq!(pick(&pool, &[]), "a");
+    }
+
+    #[test]
+    fn test_least_recently_used_strategy() {
+        let pool = pool_with(SelectionStrategy::LeastRecentlyUsed, |_| {});
+        let picks: Vec<String> = (0..4).map(|_| pick(&pool, &[])).collect();
+        assert_eq!(picks, vec!["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_fewest_errors_strategy() {
+        let pool = pool_with(SelectionStrategy::FewestErrors, |_| {});
+        pool.record_error("a");
+        pool.record_error("a");
+        pool.record_error("b");
+        assert_eq!(pick(&pool, &[]), "c");
+
+        pool.record_error("c");
+        pool.record_error("c");
+        assert_eq!(pick(&pool, &[]), "b");
+    }
+
+    #[test]
+    fn test_model_routes_to_groups() {
+        let pool = pool_with(SelectionStrategy::RoundRobin, |acc| {
+            if acc.id == "b" {
+                acc.groups = vec!["claude-capable".to_string()];
+            }
+        })
+        .with_routes(vec![ModelRoute {
+            model: "claude-*".to_string(),
+            group: "group:claude-capable".to_string(),
+        }]);
+
+        let mut failed = HashSet::new();
+        let select = |model: &str, failed: &mut HashSet<String>| {
+            pool.select("routes", failed, false, model).map(|a| a.id)
+        };
+        assert_eq!(select("claude-sonnet-4-5", &mut failed), Some("b".to_string()));
+        // Unrouted models use every account
+        assert_eq!(select("gemini-2.5-pro", &mut failed), Some("b".to_string()));
+        assert_eq!(pick(&pool, &["b"]), "c");
+
+        // Even when the group's only account failed, other accounts are never used
+        failed.insert("b@example.com".to_string());
+        assert_eq!(select("claude-opus-4-5-thinking", &mut failed), Some("b".to_string()));
+
+        let mut disabled = pool.snapshot().as_ref().clone();
+        disabled[1].disabled = true;
+        let pool = AccountPool::new(disabled).with_routes(pool.routes.clone());
+        assert!(pool.select("routes", &mut HashSet::new(), false, "claude-sonnet-4-5").is_none());
+    }
+
+    #[test]
+    fn test_update_keeps_on_disk_edits() {
+        use crate::config::account::{load_account_in, save_account_in};
+
+        let dir = std::env::temp_dir().join(format!("drovity-pool-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        save_account_in(&dir, &account("a", 3600)).unwrap();
+        let pool = AccountPool::new(vec![account("a", 3600)]).with_accounts_dir(dir.clone());
+
+        // `drovity accounts disable` ran after the pool last reloaded
+        let mut edited = load_account_in(&dir, "a").unwrap().unwrap();
+        edited.disabled = true;
+        save_account_in(&dir, &edited).unwrap();
+
+        // A token refresh / project cache write from the daemon must not undo it
+        pool.update("a", |slot| {
+            slot.token = TokenData::new("refreshed".to_string(), "refresh".to_string(), 3600);
+            slot.project_id = Some("proj".to_string());
+            true
+        });
+
+        let on_disk = load_account_in(&dir, "a").unwrap().unwrap();
+        assert!(on_disk.disabled);
+        assert_eq!(on_disk.token.access_token, "refreshed");
+        assert_eq!(on_disk.project_id.as_deref(), Some("proj"));
+        assert!(pool.get("a").unwrap().disabled);
+
+        // Deleted accounts are not resurrected
+        std::fs::remove_file(dir.join("a.json")).unwrap();
+        assert!(pool.update("a", |_| true).is_none());
+        assert!(load_account_in(&dir, "a").unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,185 @@
+// Client authentication for proxy routes
+// Accepts the configured API key in any of the header styles our clients use
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Expected API key shared by the auth middleware
+#[derive(Clone)]
+pub struct AuthState {
+    api_key: Arc<String>,
+}
+
+impl AuthState {
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: Arc::new(api_key.to_string()),
+        }
+    }
+
+    /// Authentication is disabled when no key is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.api_key.is_empty()
+    }
+}
+
+/// Extract the client key from the supported headers.
+///
+/// Priority: `Authorization: Bearer` (OpenAI) > `x-api-key` (Anthropic) > `x-goog-api-key` (Gemini)
+pub fn extract_client_key(headers: &HeaderMap) -> Option<String> {
+    if let Some(auth) = headers.get("authorization").and_then(|v| v.to_str().ok()) {
+        // Other schemes (Basic, ...) are not ours, fall through to the key headers
+        let auth = auth.trim();
+        let token = auth
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("bearer "))
+            .map(|_| auth[7..].trim());
+        if let Some(token) = token.filter(|t| !t.is_empty()) {
+            return Some(token.to_string());
+        }
+    }
+
+    for name in ["x-api-key", "x-goog-api-key"] {
+        if let Some(key) = headers.get(name).and_then(|v| v.to_str().ok()) {
+            let key = key.trim();
+            if !key.is_empty() {
+                return Some(key.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// Compare keys without short-circuiting on the first mismatched byte
+fn keys_match(provided: &str, expected: &str) -> bool {
+    let a = provided.as_bytes();
+    let b = expected.as_bytes();
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Build a 401 response in the error shape the calling protocol expects
+fn unauthorized_response(path: &str, message: &str) -> Response {
+    let body = if path.starts_with("/v1/messages") {
+        // Anthropic clients
+        json!({
+            "type": "error",
+            "error": {
+                "type": "authentication_error",
+                "message": message
+            }
+        })
+    } else {
+        // OpenAI clients
+        json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "invalid_api_key"
+            }
+        })
+    };
+
+    (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+}
+
+/// Axum middleware rejecting requests without a valid API key
+pub async fn auth_middleware(
+    State(auth): State<AuthState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !auth.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path().to_string();
+
+    match extract_client_key(request.headers()) {
+        Some(key) if keys_match(&key, &auth.api_key) => next.run(request).await,
+        Some(_) => {
+            tracing::warn!("🔒 Rejected request to {}: invalid API key", path);
+            unauthorized_response(&path, "Invalid API key")
+        }
+        None => {
+            tracing::warn!("🔒 Rejected request to {}: missing API key", path);
+            unauthorized_response(
+                &path,
+                "Missing API key. Provide it via 'Authorization: Bearer', 'x-api-key' or 'x-goog-api-key' header",
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_extract_client_key_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_client_key(&headers), None);
+
+        headers.insert("x-goog-api-key", HeaderValue::from_static("goog-key"));
+        assert_eq!(extract_client_key(&headers), Some("goog-key".to_string()));
+
+        headers.insert("x-api-key", HeaderValue::from_static("anthropic-key"));
+        assert_eq!(extract_client_key(&headers), Some("anthropic-key".to_string()));
+
+        headers.insert("authorization", HeaderValue::from_static("Bearer sk-openai"));
+        assert_eq!(extract_client_key(&headers), Some("sk-openai".to_string()));
+
+        headers.insert("authorization", HeaderValue::from_static("BEARER sk-upper"));
+        assert_eq!(extract_client_key(&headers), Some("sk-upper".to_string()));
+    }
+
+    #[test]
+    fn test_non_bearer_authorization_ignored() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        headers.insert("x-api-key", HeaderValue::from_static("anthropic-key"));
+        assert_eq!(extract_client_key(&headers), Some("anthropic-key".to_string()));
+
+        headers.remove("x-api-key");
+        assert_eq!(extract_client_key(&headers), None);
+    }
+
+    #[test]
+    fn test_keys_match() {
+        assert!(keys_match("sk-abc", "sk-abc"));
+        assert!(!keys_match("sk-abd", "sk-abc"));
+        assert!(!keys_match("sk-ab", "sk-abc"));
+    }
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_shapes() {
+        let claude = unauthorized_response("/v1/messages", "nope");
+        assert_eq!(claude.status(), StatusCode::UNAUTHORIZED);
+        let body = body_json(claude).await;
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "authentication_error");
+
+        let openai = unauthorized_response("/v1/chat/completions", "nope");
+        assert_eq!(openai.status(), StatusCode::UNAUTHORIZED);
+        let body = body_json(openai).await;
+        assert_eq!(body["error"]["code"], "invalid_api_key");
+        assert_eq!(body["error"]["message"], "nope");
+    }
+}
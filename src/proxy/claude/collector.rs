@@ -191,6 +191,9 @@ where
                     if let Some(stop_reason) = delta.get("stop_reason").and_then(|v| v.as_str()) {
                         response.stop_reason = stop_reason.to_string();
                     }
+                    if let Some(stop_sequence) = delta.get("stop_sequence").and_then(|v| v.as_str()) {
+                        response.stop_sequence = Some(stop_sequence.to_string());
+                    }
                 }
                 if let Some(usage) = event.data.get("usage") {
                     if let Ok(u) = serde_json::from_value::<Usage>(usage.clone()) {
@@ -254,4 +257,24 @@ mod tests {
             panic!("Expected Text block");
         }
     }
+
+    #[tokio::test]
+    async fn test_collect_stop_sequence() {
+        let sse_data = vec![
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_456\",\"type\":\"message\",\"role\":\"assistant\",\"model\":\"claude-sonnet-4-5\",\"content\":[],\"stop_reason\":null,\"usage\":{\"input_tokens\":10,\"output_tokens\":0}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"1, 2, \"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"stop_sequence\",\"stop_sequence\":\"3\"},\"usage\":{\"output_tokens\":5}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ];
+
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_stream_to_json(byte_stream).await.unwrap();
+        assert_eq!(response.stop_reason, "stop_sequence");
+        assert_eq!(response.stop_sequence.as_deref(), Some("3"));
+    }
 }
@@ -8,14 +8,16 @@ pub mod streaming;
 pub mod utils;
 pub mod thinking_utils;
 pub mod collector;
+pub mod token_count;
 
 pub use models::*;
 pub use request::transform_claude_request_in;
 pub use response::transform_response;
-pub use streaming::{PartProcessor, StreamingState};
+pub use streaming::{PartProcessor, StreamOptions, StreamingState};
 pub use thinking_utils::close_tool_loop_for_thinking;
 pub use collector::collect_stream_to_json;
 
+use crate::proxy::error::ClientProtocol;
 use bytes::Bytes;
 use futures::Stream;
 use std::pin::Pin;
@@ -25,16 +27,17 @@ pub fn create_claude_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     trace_id: String,
     email: String,
+    options: StreamOptions,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     use async_stream::stream;
     use bytes::BytesMut;
     use futures::StreamExt;
 
     Box::pin(stream! {
-        let mut state = StreamingState::new();
+        let mut state = StreamingState::with_options(options);
         let mut buffer = BytesMut::new();
 
-        while let Some(chunk_result) = gemini_stream.next().await {
+        'outer: while let Some(chunk_result) = gemini_stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
                     buffer.extend_from_slice(&chunk);
@@ -51,12 +54,22 @@ pub fn create_claude_sse_stream(
                                     yield Ok(sse_chunk);
                                 }
                             }
+
+                            // 命中 stop sequence: 立即结束并断开上游
+                            if state.stop_sequence_matched() && !state.message_stop_sent {
+                                let usage = state.last_usage.clone();
+                                for sse_chunk in state.emit_finish(Some("STOP"), usage.as_ref()) {
+                                    yield Ok(sse_chunk);
+                                }
+                                break 'outer;
+                            }
                         }
                     }
                 }
                 Err(e) => {
-                    yield Err(format!("Stream error: {}", e));
-                    break;
+                    // Headers are already sent: report in-band and skip the normal termination
+                    yield Ok(ClientProtocol::Anthropic.sse_error_event(&format!("Upstream stream error: {}", e)));
+                    return;
                 }
             }
         }
@@ -98,6 +111,13 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     // 解包 response 字段 (如果存在)
     let raw_json = json_value.get("response").unwrap_or(&json_value);
 
+    if let Some(usage) = raw_json
+        .get("usageMetadata")
+        .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+    {
+        state.last_usage = Some(usage);
+    }
+
     // 发送 message_start
     if !state.message_start_sent {
         chunks.push(state.emit_message_start(raw_json));
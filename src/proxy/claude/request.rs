@@ -110,6 +110,53 @@ fn clean_cache_control_from_messages(messages: &mut [Message]) {
 pub fn transform_claude_request_in(
     claude_req: &ClaudeRequest,
     project_id: &str,
+) -> Result<Value, String> {
+    transform_claude_request_for_session(claude_req, project_id, &resolve_session_id(claude_req, None))
+}
+
+/// 解析会话 ID (用于隔离 thought_signature 存储)
+///
+/// Priority: explicit `X-Drovity-Session` header, then `metadata.user_id`,
+/// then a hash of the first user turn (stable across the whole conversation).
+pub fn resolve_session_id(claude_req: &ClaudeRequest, header: Option<&str>) -> String {
+    if let Some(session) = header.map(str::trim).filter(|h| !h.is_empty()) {
+        return format!("header:{}", session);
+    }
+
+    if let Some(user_id) = claude_req
+        .metadata
+        .as_ref()
+        .and_then(|m| m.user_id.as_deref())
+        .filter(|u| !u.is_empty())
+    {
+        return format!("user:{}", user_id);
+    }
+
+    let first_turn = claude_req
+        .messages
+        .iter()
+        .find(|m| m.role == "user")
+        .map(|m| match &m.content {
+            MessageContent::String(text) => text.clone(),
+            MessageContent::Array(blocks) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .unwrap_or_default();
+
+    format!("turn:{}", crate::proxy::common::utils::short_hash(&first_turn))
+}
+
+/// 转换 Claude 请求, thought_signature 回退仅使用指定会话的存储
+pub fn transform_claude_request_for_session(
+    claude_req: &ClaudeRequest,
+    project_id: &str,
+    session_id: &str,
 ) -> Result<Value, String> {
     // [CRITICAL FIX] 预先清理所有消息中的 cache_control 字段
     // 这解决了 VS Code 插件等客户端在多轮对话中将历史消息的 cache_control 字段
@@ -204,7 +251,7 @@ pub fn transform_claude_request_in(
     // [FIX #295 & #298] If thinking enabled but no signature available,
     // disable thinking to prevent Gemini 3 Pro rejection
     if is_thinking_enabled {
-        let global_sig = get_thought_signature();
+        let session_sig = get_thought_signature(session_id);
         
         // Check if there are any thinking blocks in message history
         let has_thinking_history = claude_req.messages.iter().any(|m| {
@@ -240,7 +287,7 @@ pub fn transform_claude_request_in(
         }
 
         if needs_signature_check
-            && !has_valid_signature_for_function_calls(&claude_req.messages, &global_sig)
+            && !has_valid_signature_for_function_calls(&claude_req.messages, &session_sig)
         {
             tracing::warn!(
                 "[Thinking-Mode] [FIX #295] No valid signature found for function calls. \
@@ -251,7 +298,7 @@ pub fn transform_claude_request_in(
     }
 
     // 4. Generation Config & Thinking (Pass final is_thinking_enabled)
-    let generation_config = build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled);
+    let generation_config = build_generation_config(claude_req, &mapped_model, has_web_search_tool, is_thinking_enabled);
 
     // 2. Contents (Messages)
     let contents = build_contents(
@@ -260,6 +307,7 @@ pub fn transform_claude_request_in(
         is_thinking_enabled,
         allow_dummy_thought,
         &mapped_model,
+        session_id,
     )?;
 
     // 3. Tools
@@ -286,13 +334,8 @@ pub fn transform_claude_request_in(
     }
 
     if let Some(tools_val) = tools {
+        inner_request["toolConfig"] = build_tool_config(&claude_req.tool_choice, &tools_val)?;
         inner_request["tools"] = tools_val;
-        // 显式设置工具配置模式为 VALIDATED
-        inner_request["toolConfig"] = json!({
-            "functionCallingConfig": {
-                "mode": "VALIDATED"
-            }
-        });
     }
 
     // Inject googleSearch tool if needed (and not already done by build_tools)
@@ -404,10 +447,10 @@ const MIN_SIGNATURE_LENGTH: usize = 50;
 /// This prevents Gemini 3 Pro from rejecting requests due to missing thought_signature
 fn has_valid_signature_for_function_calls(
     messages: &[Message],
-    global_sig: &Option<String>,
+    session_sig: &Option<String>,
 ) -> bool {
-    // 1. Check global store
-    if let Some(sig) = global_sig {
+    // 1. Check session store
+    if let Some(sig) = session_sig {
         if sig.len() >= MIN_SIGNATURE_LENGTH {
             return true;
         }
@@ -503,6 +546,7 @@ fn build_contents(
     is_thinking_enabled: bool,
     allow_dummy_thought: bool,
     mapped_model: &str,
+    session_id: &str,
 ) -> Result<Value, String> {
     let mut contents = Vec::new();
     let mut last_thought_signature: Option<String> = None;
@@ -697,7 +741,7 @@ fn build_contents(
                             // 存储 id -> name 映射
                             tool_id_to_name.insert(id.clone(), name.clone());
 
-                            // Signature resolution logic (Priority: Client -> Context -> Cache -> Global Store)
+                            // Signature resolution logic (Priority: Client -> Context -> Cache -> Session Store)
                             // [CRITICAL FIX] Do NOT use skip_thought_signature_validator for Vertex AI
                             // Vertex AI rejects this sentinel value, so we only add thoughtSignature if we have a real one
                             let final_sig = signature.as_ref()
@@ -712,12 +756,11 @@ fn build_contents(
                                         })
                                 })
                                 .or_else(|| {
-                                    let global_sig = get_thought_signature();
-                                    if global_sig.is_some() {
-                                        tracing::info!("[Claude-Request] Using global thought_signature fallback (length: {})", 
-                                            global_sig.as_ref().unwrap().len());
+                                    let session_sig = get_thought_signature(session_id);
+                                    if let Some(sig) = &session_sig {
+                                        tracing::info!("[Claude-Request] Using session thought_signature fallback (length: {})", sig.len());
                                     }
-                                    global_sig
+                                    session_sig
                                 });
                             // Only add thoughtSignature if we have a valid one
                             // Do NOT add skip_thought_signature_validator - Vertex AI rejects it
@@ -948,7 +991,7 @@ fn build_contents(
 }
 
 /// Merge adjacent messages with the same role
-fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
+pub fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
     if contents.is_empty() {
         return contents;
     }
@@ -976,6 +1019,38 @@ fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
     merged
 }
 
+/// 构建 toolConfig (tool_choice -> functionCallingConfig)
+fn build_tool_config(tool_choice: &Option<ToolChoice>, tools: &Value) -> Result<Value, String> {
+    let declared_names: Vec<&str> = tools
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.get("functionDeclarations").and_then(|d| d.as_array()))
+        .flatten()
+        .filter_map(|d| d.get("name").and_then(|n| n.as_str()))
+        .collect();
+
+    // googleSearch 等内置工具不支持 functionCallingConfig 模式切换
+    if declared_names.is_empty() {
+        return Ok(json!({ "functionCallingConfig": { "mode": "VALIDATED" } }));
+    }
+
+    let config = match tool_choice {
+        // 默认显式设置为 VALIDATED
+        None | Some(ToolChoice::Auto { .. }) => json!({ "mode": "VALIDATED" }),
+        Some(ToolChoice::Any { .. }) => json!({ "mode": "ANY" }),
+        Some(ToolChoice::None) => json!({ "mode": "NONE" }),
+        Some(ToolChoice::Tool { name, .. }) => {
+            if !declared_names.contains(&name.as_str()) {
+                return Err(format!("tool_choice: tool '{}' is not defined in tools", name));
+            }
+            json!({ "mode": "ANY", "allowedFunctionNames": [name] })
+        }
+    };
+
+    Ok(json!({ "functionCallingConfig": config }))
+}
+
 /// 构建 Tools
 fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option<Value>, String> {
     if let Some(tools_list) = tools {
@@ -1051,6 +1126,7 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
 /// 构建 Generation Config
 fn build_generation_config(
     claude_req: &ClaudeRequest,
+    mapped_model: &str,
     has_web_search: bool,
     is_thinking_enabled: bool
 ) -> Value {
@@ -1104,6 +1180,18 @@ fn build_generation_config(
                 config["effortLevel"]
             );
         }
+
+        // Structured output: json_schema -> responseMimeType + responseSchema
+        if let Some(format) = &output_config.format {
+            if format.type_ == "json_schema" {
+                config["responseMimeType"] = json!("application/json");
+                if let Some(schema) = &format.schema {
+                    let mut response_schema = schema.clone();
+                    crate::proxy::common::json_schema::clean_json_schema(&mut response_schema);
+                    config["responseSchema"] = response_schema;
+                }
+            }
+        }
     }
 
     // web_search 强制 candidateCount=1
@@ -1111,21 +1199,66 @@ fn build_generation_config(
         config["candidateCount"] = json!(1);
     }*/
 
-    // max_tokens 映射为 maxOutputTokens
-    config["maxOutputTokens"] = json!(64000);
+    // max_tokens 映射为 maxOutputTokens (按模型上限截断)
+    let model_limit = crate::proxy::common::model_mapping::max_output_tokens_for_model(mapped_model);
+    let max_output_tokens = claude_req.max_tokens.map_or(model_limit, |t| t.min(model_limit));
+    if claude_req.max_tokens.is_some_and(|t| t > model_limit) {
+        tracing::debug!(
+            "[Generation-Config] max_tokens {:?} clamped to {} for {}",
+            claude_req.max_tokens,
+            model_limit,
+            mapped_model
+        );
+    }
+    config["maxOutputTokens"] = json!(max_output_tokens);
 
-    // [优化] 设置全局停止序列,防止流式输出冗余
-    config["stopSequences"] = json!([
-        "<|user|>",
-        "<|endoftext|>",
-        "<|end_of_turn|>",
-        "[DONE]",
-        "\n\nHuman:"
-    ]);
+    // Gemini 要求 thinkingBudget < maxOutputTokens, 截断后的 max_tokens 可能更小
+    if let Some(budget) = config["thinkingConfig"]["thinkingBudget"].as_u64() {
+        let limit = u64::from(max_output_tokens.saturating_sub(1));
+        if budget > limit {
+            tracing::debug!(
+                "[Generation-Config] thinkingBudget {} clamped to {} (maxOutputTokens {})",
+                budget,
+                limit,
+                max_output_tokens
+            );
+            config["thinkingConfig"]["thinkingBudget"] = json!(limit);
+        }
+    }
+
+    config["stopSequences"] = json!(merge_stop_sequences(claude_req.stop_sequences.as_deref()));
 
     config
 }
 
+/// Gemini accepts at most 5 stop sequences
+const MAX_STOP_SEQUENCES: usize = 5;
+
+/// [优化] 全局停止序列,防止流式输出冗余
+const DEFAULT_STOP_SEQUENCES: [&str; 5] = ["<|user|>", "<|endoftext|>", "<|end_of_turn|>", "[DONE]", "\n\nHuman:"];
+
+/// 客户端 stop_sequences 优先, 剩余位置用默认序列填充
+///
+/// 上游在命中处停止生成 (不再计费), 但既不返回命中的序列也不区分 finishReason,
+/// 这种情况下响应只能报告 end_turn。StreamingState 的本地检测仍覆盖超出上限的序列。
+fn merge_stop_sequences(client: Option<&[String]>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    let candidates = client
+        .unwrap_or_default()
+        .iter()
+        .map(String::as_str)
+        .chain(DEFAULT_STOP_SEQUENCES);
+    for sequence in candidates {
+        if merged.len() == MAX_STOP_SEQUENCES {
+            break;
+        }
+        if !sequence.is_empty() && !merged.iter().any(|s| s == sequence) {
+            merged.push(sequence.to_string());
+        }
+    }
+    merged
+}
+
 /// Recursively remove 'thought' and 'thoughtSignature' fields
 /// Used when downgrading thinking (e.g. during 400 retry)
 pub fn clean_thinking_fields_recursive(val: &mut Value) {
@@ -1133,6 +1266,7 @@ pub fn clean_thinking_fields_recursive(val: &mut Value) {
         Value::Object(map) => {
             map.remove("thought");
             map.remove("thoughtSignature");
+            map.remove("thought_signature");
             for (_, v) in map.iter_mut() {
                 clean_thinking_fields_recursive(v);
             }
@@ -1146,6 +1280,47 @@ pub fn clean_thinking_fields_recursive(val: &mut Value) {
     }
 }
 
+/// 上游是否因 thinking 签名拒绝了请求 (400), 可关闭 thinking 后重试
+pub fn is_thinking_signature_error(message: &str) -> bool {
+    const PATTERNS: [&str; 6] = [
+        "invalid signature",
+        "invalid `signature`",
+        "signature is invalid",
+        "corrupted thought signature",
+        "thought signature is not valid",
+        "must start with a thinking block",
+    ];
+    let message = message.to_lowercase();
+    PATTERNS.iter().any(|p| message.contains(p))
+}
+
+/// 关闭 thinking: 移除 thinkingConfig、thought 文本块和所有签名
+///
+/// Applied to a transformed v1internal body when upstream rejects our signatures,
+/// so the same conversation can be retried as a plain (non-thinking) request.
+pub fn downgrade_thinking(body: &mut Value) {
+    let request = match body.get("request") {
+        Some(_) => &mut body["request"],
+        None => body,
+    };
+
+    if let Some(config) = request.get_mut("generationConfig").and_then(|c| c.as_object_mut()) {
+        config.remove("thinkingConfig");
+    }
+
+    if let Some(contents) = request.get_mut("contents").and_then(|c| c.as_array_mut()) {
+        for content in contents.iter_mut() {
+            if let Some(parts) = content.get_mut("parts").and_then(|p| p.as_array_mut()) {
+                parts.retain(|part| part.get("thought") != Some(&json!(true)));
+            }
+        }
+        // Turns that only held thinking are now empty
+        contents.retain(|c| c["parts"].as_array().is_some_and(|p| !p.is_empty()));
+        let merged = merge_adjacent_roles(std::mem::take(contents));
+        *contents = merged;
+        clean_thinking_fields_recursive(&mut request["contents"]);
+    }
+}
 
 /// Check if a string is already Base64 encoded
 fn is_base64(s: &str) -> bool {
@@ -1202,6 +1377,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1212,6 +1389,157 @@ mod tests {
         assert!(body["requestId"].as_str().unwrap().starts_with("agent-"));
     }
 
+    #[test]
+    fn test_output_format_json_schema() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "List colors" }],
+            "output_config": {
+                "format": {
+                    "type": "json_schema",
+                    "schema": {
+                        "type": "object",
+                        "properties": { "colors": { "type": "array", "items": { "type": "string" } } },
+                        "required": ["colors"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(config["responseSchema"]["properties"]["colors"]["type"], "array");
+        assert!(config["responseSchema"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_max_tokens_clamped_to_model_limit() {
+        let mut req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 1024,
+            "stop_sequences": ["END"]
+        }))
+        .unwrap();
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["maxOutputTokens"], 1024);
+        // Client sequences go upstream first, defaults fill the remaining slots
+        let stops = config["stopSequences"].as_array().unwrap();
+        assert_eq!(stops[0], "END");
+        assert_eq!(stops.len(), 5);
+
+        req.max_tokens = Some(200_000);
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["generationConfig"]["maxOutputTokens"], 65535);
+
+    }
+
+    #[test]
+    fn test_thinking_budget_below_max_tokens() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5-thinking",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 2048,
+            "thinking": { "type": "enabled", "budget_tokens": 4096 }
+        }))
+        .unwrap();
+
+        let config = build_generation_config(&req, "claude-sonnet-4-5-thinking", false, true);
+        assert_eq!(config["maxOutputTokens"], 2048);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 2047);
+    }
+
+    #[test]
+    fn test_downgrade_thinking() {
+        let mut body = json!({
+            "model": "gemini-3-pro",
+            "request": {
+                "generationConfig": { "maxOutputTokens": 1024, "thinkingConfig": { "includeThoughts": true } },
+                "contents": [
+                    { "role": "user", "parts": [{ "text": "hi" }] },
+                    { "role": "model", "parts": [{ "text": "pondering", "thought": true, "thoughtSignature": "sig" }] },
+                    { "role": "model", "parts": [{ "functionCall": { "name": "f", "args": {} }, "thoughtSignature": "sig", "thought_signature": "sig" }] }
+                ]
+            }
+        });
+
+        downgrade_thinking(&mut body);
+        let request = &body["request"];
+        assert!(request["generationConfig"].get("thinkingConfig").is_none());
+        assert_eq!(request["generationConfig"]["maxOutputTokens"], 1024);
+
+        let contents = request["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[1]["parts"].as_array().unwrap().len(), 1);
+        assert!(!serde_json::to_string(&body).unwrap().contains("sig"));
+
+        assert!(is_thinking_signature_error("Gemini API error 400: {\"message\": \"Invalid signature in thinking block\"}"));
+        assert!(is_thinking_signature_error("messages.1.content.0.type: Expected `thinking`, final assistant message must start with a thinking block"));
+        assert!(!is_thinking_signature_error("Gemini API error 400: invalid argument: temperature"));
+    }
+
+    #[test]
+    fn test_resolve_session_id() {
+        let mut req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                { "role": "user", "content": "first question" },
+                { "role": "assistant", "content": "answer" },
+                { "role": "user", "content": "follow-up" }
+            ]
+        }))
+        .unwrap();
+
+        // First user turn hash is stable as the conversation grows
+        let by_turn = resolve_session_id(&req, None);
+        assert!(by_turn.starts_with("turn:"));
+        req.messages.truncate(1);
+        assert_eq!(resolve_session_id(&req, None), by_turn);
+
+        req.metadata = Some(Metadata { user_id: Some("user_abc".to_string()) });
+        assert_eq!(resolve_session_id(&req, None), "user:user_abc");
+        assert_eq!(resolve_session_id(&req, Some("droid-1")), "header:droid-1");
+    }
+
+    #[test]
+    fn test_tool_choice_mapping() {
+        let mut req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": "extract" }],
+            "tools": [
+                { "name": "extract", "input_schema": { "type": "object", "properties": {} } },
+                { "name": "search", "input_schema": { "type": "object", "properties": {} } }
+            ],
+            "tool_choice": { "type": "tool", "name": "extract", "disable_parallel_tool_use": true }
+        }))
+        .unwrap();
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let config = &body["request"]["toolConfig"]["functionCallingConfig"];
+        assert_eq!(config["mode"], "ANY");
+        assert_eq!(config["allowedFunctionNames"], json!(["extract"]));
+
+        req.tool_choice = Some(ToolChoice::None);
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["toolConfig"]["functionCallingConfig"], json!({ "mode": "NONE" }));
+
+        req.tool_choice = Some(ToolChoice::Any { disable_parallel_tool_use: None });
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["toolConfig"]["functionCallingConfig"], json!({ "mode": "ANY" }));
+
+        req.tool_choice = None;
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["toolConfig"]["functionCallingConfig"]["mode"], "VALIDATED");
+
+        req.tool_choice = Some(ToolChoice::Tool { name: "missing".to_string(), disable_parallel_tool_use: None });
+        assert!(transform_claude_request_in(&req, "test-project").is_err());
+    }
+
     #[test]
     fn test_clean_json_schema() {
         let mut schema = json!({
@@ -1299,6 +1627,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1369,6 +1699,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1444,6 +1776,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1493,6 +1827,8 @@ mod tests {
             thinking: None, // 未启用 thinking
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1546,6 +1882,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1586,6 +1924,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            stop_sequences: None,
+            tool_choice: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
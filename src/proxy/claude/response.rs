@@ -426,6 +426,7 @@ mod tests {
                 candidates_token_count: Some(5),
                 total_token_count: Some(15),
                 cached_content_token_count: None,
+                thoughts_token_count: None,
             }),
             model_version: Some("gemini-2.5-pro".to_string()),
             response_id: Some("resp_123".to_string()),
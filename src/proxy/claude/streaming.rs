@@ -3,7 +3,7 @@
 
 use super::models::*;
 use super::utils::to_claude_usage;
-// use crate::proxy::mappers::signature_store::store_thought_signature; // Deprecated
+use crate::proxy::mappers::signature_store::store_thought_signature;
 use crate::proxy::SignatureCache;
 use bytes::Bytes;
 use serde_json::json;
@@ -140,6 +140,28 @@ impl SignatureManager {
     }
 }
 
+/// 客户端请求中影响流输出的选项
+#[derive(Debug, Clone, Default)]
+pub struct StreamOptions {
+    pub stop_sequences: Vec<String>,
+    pub disable_parallel_tool_use: bool,
+    /// 会话 ID, 捕获的 thought_signature 存入该会话
+    pub session_id: Option<String>,
+}
+
+impl StreamOptions {
+    pub fn from_request(req: &ClaudeRequest, session_id: &str) -> Self {
+        Self {
+            session_id: Some(session_id.to_string()),
+            stop_sequences: req.stop_sequences.clone().unwrap_or_default(),
+            disable_parallel_tool_use: req
+                .tool_choice
+                .as_ref()
+                .is_some_and(|c| c.disable_parallel_tool_use()),
+        }
+    }
+}
+
 /// 流式状态机
 pub struct StreamingState {
     block_type: BlockType,
@@ -158,6 +180,17 @@ pub struct StreamingState {
     last_valid_state: Option<BlockType>,
     // [NEW] Model tracking for signature cache
     pub model_name: Option<String>,
+    /// 客户端 stop_sequences (本地检测, Gemini 不返回命中的序列)
+    stop_sequences: Vec<String>,
+    /// 可能是 stop sequence 前缀的文本, 暂不发送
+    pending_text: String,
+    /// 命中的 stop sequence
+    matched_stop_sequence: Option<String>,
+    /// 最近一次 usageMetadata (提前结束时使用)
+    pub last_usage: Option<UsageMetadata>,
+    /// 只保留第一个 functionCall
+    disable_parallel_tool_use: bool,
+    session_id: Option<String>,
 }
 
 impl StreamingState {
@@ -176,7 +209,72 @@ impl StreamingState {
             parse_error_count: 0,
             last_valid_state: None,
             model_name: None,
+            stop_sequences: Vec::new(),
+            pending_text: String::new(),
+            matched_stop_sequence: None,
+            last_usage: None,
+            disable_parallel_tool_use: false,
+            session_id: None,
+        }
+    }
+
+    /// 应用客户端选项
+    pub fn with_options(options: StreamOptions) -> Self {
+        let mut state = Self::new();
+        state.stop_sequences = options.stop_sequences.into_iter().filter(|s| !s.is_empty()).collect();
+        state.disable_parallel_tool_use = options.disable_parallel_tool_use;
+        state.session_id = options.session_id;
+        state
+    }
+
+    /// 存入当前会话的 thought_signature 存储
+    fn store_session_signature(&self, signature: &str) {
+        if let Some(session_id) = &self.session_id {
+            store_thought_signature(session_id, signature);
+        }
+    }
+
+    /// 是否已命中 stop sequence (之后的输出全部丢弃)
+    pub fn stop_sequence_matched(&self) -> bool {
+        self.matched_stop_sequence.is_some()
+    }
+
+    /// 过滤文本: 截断到命中的 stop sequence, 并暂存可能构成 stop sequence 前缀的尾部
+    pub fn apply_stop_sequences(&mut self, text: &str) -> String {
+        if self.stop_sequences.is_empty() {
+            return text.to_string();
+        }
+
+        let mut buffer = std::mem::take(&mut self.pending_text);
+        buffer.push_str(text);
+
+        // Earliest match wins
+        if let Some((pos, sequence)) = self
+            .stop_sequences
+            .iter()
+            .filter_map(|seq| buffer.find(seq.as_str()).map(|pos| (pos, seq)))
+            .min_by_key(|(pos, _)| *pos)
+        {
+            self.matched_stop_sequence = Some(sequence.clone());
+            buffer.truncate(pos);
+            return buffer;
         }
+
+        let holdback = self
+            .stop_sequences
+            .iter()
+            .map(|seq| partial_match_len(&buffer, seq))
+            .max()
+            .unwrap_or(0);
+        let split = buffer.len() - holdback;
+        self.pending_text = buffer[split..].to_string();
+        buffer.truncate(split);
+        buffer
+    }
+
+    /// 是否有暂存文本
+    pub fn has_pending_text(&self) -> bool {
+        !self.pending_text.is_empty()
     }
 
     /// 发送 SSE 事件
@@ -267,6 +365,12 @@ impl StreamingState {
 
         let mut chunks = Vec::new();
 
+        // Text 块结束时发送暂存的文本 (未构成 stop sequence)
+        if self.block_type == BlockType::Text && !self.pending_text.is_empty() {
+            let pending = std::mem::take(&mut self.pending_text);
+            chunks.push(self.emit_delta("text_delta", json!({ "text": pending })));
+        }
+
         // Thinking 块结束时发送暂存的签名
         if self.block_type == BlockType::Thinking && self.signatures.has_pending() {
             if let Some(signature) = self.signatures.consume() {
@@ -383,7 +487,9 @@ impl StreamingState {
         }
 
         // 确定 stop_reason
-        let stop_reason = if self.used_tool {
+        let stop_reason = if self.matched_stop_sequence.is_some() {
+            "stop_sequence"
+        } else if self.used_tool {
             "tool_use"
         } else if finish_reason == Some("MAX_TOKENS") {
             "max_tokens"
@@ -405,7 +511,7 @@ impl StreamingState {
             "message_delta",
             json!({
                 "type": "message_delta",
-                "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                "delta": { "stop_reason": stop_reason, "stop_sequence": self.matched_stop_sequence },
                 "usage": usage
             }),
         ));
@@ -520,6 +626,14 @@ impl StreamingState {
     }
 }
 
+/// `text` 末尾与 `sequence` 前缀重合的最大长度 (不含完整匹配)
+fn partial_match_len(text: &str, sequence: &str) -> usize {
+    (1..sequence.len())
+        .rev()
+        .find(|&len| sequence.is_char_boundary(len) && text.ends_with(&sequence[..len]))
+        .unwrap_or(0)
+}
+
 /// Part 处理器
 pub struct PartProcessor<'a> {
     state: &'a mut StreamingState,
@@ -533,6 +647,10 @@ impl<'a> PartProcessor<'a> {
     /// 处理单个 part
     pub fn process(&mut self, part: &GeminiPart) -> Vec<Bytes> {
         let mut chunks = Vec::new();
+        // 已命中 stop sequence, 丢弃后续输出
+        if self.state.stop_sequence_matched() {
+            return chunks;
+        }
         // [FIX #545] Decode Base64 signature if present (Gemini sends Base64, Claude expects Raw)
         let signature = part.thought_signature.as_ref().map(|sig| {
              // Try to decode as base64
@@ -553,6 +671,12 @@ impl<'a> PartProcessor<'a> {
 
         // 1. FunctionCall 处理
         if let Some(fc) = &part.function_call {
+            // disable_parallel_tool_use: 丢弃第一个之后的 functionCall
+            if self.state.disable_parallel_tool_use && self.state.used_tool {
+                tracing::debug!("[Streaming] Dropping parallel function call '{}'", fc.name);
+                return chunks;
+            }
+
             // 先处理 trailingSignature (B4/C3 场景)
             if self.state.has_trailing_signature() {
                 chunks.extend(self.state.end_block());
@@ -654,6 +778,8 @@ impl<'a> PartProcessor<'a> {
             if let Some(model) = &self.state.model_name {
                  SignatureCache::global().cache_thinking_family(sig.clone(), model.clone());
             }
+            // 2. Session fallback for clients that strip signatures
+            self.state.store_session_signature(sig);
             
             tracing::debug!(
                 "[Claude-SSE] Captured thought_signature from thinking block (length: {})",
@@ -703,6 +829,8 @@ impl<'a> PartProcessor<'a> {
             }
         }
 
+        let text = &self.state.apply_stop_sequences(text);
+
         // 非空 text 带签名 - 立即处理
         if signature.is_some() {
             // 2. 开始新 text 块并发送内容
@@ -736,6 +864,9 @@ impl<'a> PartProcessor<'a> {
         }
 
         // 普通 text (无签名)
+        if text.is_empty() && !self.state.has_pending_text() {
+            return chunks;
+        }
         if self.state.current_block_type() != BlockType::Text {
             chunks.extend(
                 self.state
@@ -743,7 +874,9 @@ impl<'a> PartProcessor<'a> {
             );
         }
 
-        chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+        if !text.is_empty() {
+            chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+        }
 
         chunks
     }
@@ -779,6 +912,7 @@ impl<'a> PartProcessor<'a> {
             
             // 2. Cache tool signature (Layer 1 recovery)
             SignatureCache::global().cache_tool_signature(&tool_id, sig.clone());
+            self.state.store_session_signature(sig);
             
              tracing::debug!(
                 "[Claude-SSE] Captured thought_signature for function call (length: {})",
@@ -825,6 +959,53 @@ mod tests {
         assert!(!mgr.has_pending());
     }
 
+    #[test]
+    fn test_stop_sequence_across_chunks() {
+        let mut state = StreamingState::with_options(StreamOptions {
+            stop_sequences: vec!["###".to_string()],
+            ..Default::default()
+        });
+
+        let text_part = |text: &str| GeminiPart {
+            text: Some(text.to_string()),
+            function_call: None,
+            inline_data: None,
+            thought: None,
+            thought_signature: None,
+            function_response: None,
+        };
+
+        let mut output = String::new();
+        for piece in ["Hello #", "# world #", "## ignored", "more"] {
+            let mut processor = PartProcessor::new(&mut state);
+            for chunk in processor.process(&text_part(piece)) {
+                output.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+        }
+        for chunk in state.emit_finish(Some("STOP"), None) {
+            output.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+
+        assert!(output.contains(r#""text":"Hello ""#));
+        assert!(output.contains(r###""text":"## world ""###));
+        assert!(!output.contains("ignored"));
+        assert!(!output.contains("more"));
+        assert!(output.contains(r#""stop_reason":"stop_sequence""#));
+        assert!(output.contains(r####""stop_sequence":"###""####));
+    }
+
+    #[test]
+    fn test_pending_prefix_flushed_without_match() {
+        let mut state = StreamingState::with_options(StreamOptions {
+            stop_sequences: vec!["END".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(state.apply_stop_sequences("THE E"), "THE ");
+        assert!(state.has_pending_text());
+        assert_eq!(state.apply_stop_sequences("ND"), "");
+        assert!(state.stop_sequence_matched());
+    }
+
     #[test]
     fn test_streaming_state_emit() {
         let state = StreamingState::new();
@@ -878,4 +1059,36 @@ mod tests {
         // 3. content_block_stop
         assert!(output.contains(r#""type":"content_block_stop""#));
     }
+
+    #[test]
+    fn test_disable_parallel_tool_use() {
+        let mut state = StreamingState::with_options(StreamOptions {
+            disable_parallel_tool_use: true,
+            ..Default::default()
+        });
+
+        let call_part = |name: &str| GeminiPart {
+            text: None,
+            function_call: Some(FunctionCall {
+                name: name.to_string(),
+                args: Some(json!({})),
+                id: None,
+            }),
+            inline_data: None,
+            thought: None,
+            thought_signature: None,
+            function_response: None,
+        };
+
+        let mut output = String::new();
+        for name in ["first_tool", "second_tool"] {
+            let mut processor = PartProcessor::new(&mut state);
+            for chunk in processor.process(&call_part(name)) {
+                output.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+        }
+
+        assert!(output.contains("first_tool"));
+        assert!(!output.contains("second_tool"));
+    }
 }
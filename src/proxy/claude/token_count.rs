@@ -0,0 +1,166 @@
+// Token 计数 (/v1/messages/count_tokens)
+// 基于转换后的 Gemini 请求计数, 保证与实际发送的 system/tools 一致
+
+use serde_json::{json, Value};
+
+/// Gemini bills each image at a flat rate (up to 384px per side)
+const IMAGE_TOKENS: u64 = 258;
+/// Extra tokens per turn for role markers
+const TURN_OVERHEAD_TOKENS: u64 = 4;
+
+/// 本地估算输入 token 数 (上游 countTokens 不可用时的回退)
+///
+/// Counts contents, systemInstruction and tools of a transformed v1internal
+/// body. ASCII text is ~4 chars per token, other scripts ~1 char per token.
+pub fn estimate_input_tokens(gemini_body: &Value) -> u64 {
+    let request = gemini_body.get("request").unwrap_or(gemini_body);
+    let mut total = 0;
+
+    if let Some(contents) = request.get("contents").and_then(|c| c.as_array()) {
+        for content in contents {
+            total += TURN_OVERHEAD_TOKENS + estimate_value(&content["parts"]);
+        }
+    }
+    if let Some(system) = request.get("systemInstruction").filter(|s| !s.is_null()) {
+        total += TURN_OVERHEAD_TOKENS + estimate_value(&system["parts"]);
+    }
+    if let Some(tools) = request.get("tools") {
+        total += estimate_value(tools);
+    }
+
+    total
+}
+
+fn estimate_value(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => estimate_text(s),
+        Value::Array(items) => items.iter().map(estimate_value).sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, v)| match key.as_str() {
+                // Signatures are opaque to the model
+                "thoughtSignature" | "thought" => 0,
+                "inlineData" => estimate_inline_data(v),
+                _ => estimate_text(key) + estimate_value(v),
+            })
+            .sum(),
+        Value::Number(n) => estimate_text(&n.to_string()),
+        Value::Bool(_) => 1,
+        Value::Null => 0,
+    }
+}
+
+fn estimate_text(text: &str) -> u64 {
+    let (ascii, other) = text
+        .chars()
+        .fold((0u64, 0u64), |(a, o), c| if c.is_ascii() { (a + 1, o) } else { (a, o + 1) });
+    ascii.div_ceil(4) + other
+}
+
+fn estimate_inline_data(inline_data: &Value) -> u64 {
+    let mime_type = inline_data["mimeType"].as_str().unwrap_or_default();
+    if mime_type.starts_with("image/") {
+        return IMAGE_TOKENS;
+    }
+    // Documents: roughly one image-equivalent per 3 KB of decoded data (about a page)
+    let decoded_bytes = inline_data["data"].as_str().map(|d| d.len() as u64 * 3 / 4).unwrap_or(0);
+    decoded_bytes.div_ceil(3 * 1024).max(1) * IMAGE_TOKENS
+}
+
+/// 构建上游 countTokens 请求体
+///
+/// countTokens only accepts `contents`, so the system prompt and tool
+/// declarations are folded in as leading text parts to be counted too.
+pub fn build_count_tokens_request(gemini_body: &Value) -> Value {
+    let request = gemini_body.get("request").unwrap_or(gemini_body);
+    let mut contents = Vec::new();
+
+    if let Some(parts) = request["systemInstruction"]["parts"].as_array() {
+        contents.push(json!({ "role": "user", "parts": parts }));
+    }
+    if let Some(tools) = request.get("tools").filter(|t| !t.is_null()) {
+        contents.push(json!({
+            "role": "user",
+            "parts": [{ "text": serde_json::to_string(tools).unwrap_or_default() }]
+        }));
+    }
+    if let Some(existing) = request["contents"].as_array() {
+        for content in existing {
+            // Strip signatures, countTokens rejects parts it cannot validate
+            let mut content = content.clone();
+            if let Some(parts) = content["parts"].as_array_mut() {
+                for part in parts {
+                    if let Some(obj) = part.as_object_mut() {
+                        obj.remove("thoughtSignature");
+                    }
+                }
+            }
+            contents.push(content);
+        }
+    }
+
+    json!({
+        "request": {
+            "model": format!("models/{}", gemini_body["model"].as_str().unwrap_or_default()),
+            "contents": contents
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_includes_system_and_tools() {
+        let base = json!({
+            "model": "gemini-2.5-flash",
+            "request": {
+                "contents": [{ "role": "user", "parts": [{ "text": "Hello there, how are you?" }] }]
+            }
+        });
+        let mut with_extras = base.clone();
+        with_extras["request"]["systemInstruction"] = json!({ "parts": [{ "text": "You are a helpful assistant." }] });
+        with_extras["request"]["tools"] = json!([{ "functionDeclarations": [{
+            "name": "get_weather",
+            "description": "Get the weather for a city",
+            "parameters": { "type": "object", "properties": { "city": { "type": "string" } } }
+        }] }]);
+
+        let base_tokens = estimate_input_tokens(&base);
+        assert!(base_tokens > 0);
+        assert!(estimate_input_tokens(&with_extras) > base_tokens + 10);
+    }
+
+    #[test]
+    fn test_estimate_images_and_signatures() {
+        let body = json!({
+            "request": {
+                "contents": [{ "role": "user", "parts": [
+                    { "inlineData": { "mimeType": "image/png", "data": "A".repeat(100_000) } },
+                    { "text": "x", "thoughtSignature": "S".repeat(4000) }
+                ] }]
+            }
+        });
+        let tokens = estimate_input_tokens(&body);
+        assert!(tokens >= IMAGE_TOKENS);
+        assert!(tokens < IMAGE_TOKENS + 20);
+    }
+
+    #[test]
+    fn test_count_tokens_request_folds_system_and_tools() {
+        let body = json!({
+            "model": "gemini-2.5-pro",
+            "request": {
+                "systemInstruction": { "parts": [{ "text": "sys" }] },
+                "tools": [{ "functionDeclarations": [{ "name": "f" }] }],
+                "contents": [{ "role": "user", "parts": [{ "text": "hi", "thoughtSignature": "sig" }] }]
+            }
+        });
+        let request = build_count_tokens_request(&body);
+        assert_eq!(request["request"]["model"], "models/gemini-2.5-pro");
+        let contents = request["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert!(contents[2]["parts"][0].get("thoughtSignature").is_none());
+    }
+}
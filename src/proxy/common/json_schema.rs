@@ -281,6 +281,110 @@ fn extract_type_from_union(union_array: &Vec<Value>) -> Option<String> {
     None
 }
 
+/// 校验 JSON 值是否符合 (原始) JSON Schema
+///
+/// 只覆盖结构化输出常用的关键字: type, properties, required, additionalProperties,
+/// items, enum, const, anyOf/oneOf/allOf 以及本地 $ref。未知关键字视为通过。
+pub fn validate_against_schema(value: &Value, schema: &Value) -> Result<(), String> {
+    validate_node(value, schema, schema, "$")
+}
+
+fn validate_node(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+    let map = match schema {
+        Value::Object(map) => map,
+        Value::Bool(false) => return Err(format!("{}: no value is allowed here", path)),
+        _ => return Ok(()),
+    };
+
+    if let Some(reference) = map.get("$ref").and_then(|r| r.as_str()) {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .ok_or_else(|| format!("{}: unresolvable $ref {}", path, reference))?;
+        return validate_node(value, target, root, path);
+    }
+
+    if let Some(branches) = map.get("anyOf").or_else(|| map.get("oneOf")).and_then(|b| b.as_array()) {
+        if !branches.iter().any(|b| validate_node(value, b, root, path).is_ok()) {
+            return Err(format!("{}: does not match any allowed schema", path));
+        }
+    }
+    if let Some(branches) = map.get("allOf").and_then(|b| b.as_array()) {
+        for branch in branches {
+            validate_node(value, branch, root, path)?;
+        }
+    }
+
+    if let Some(allowed) = map.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            return Err(format!("{}: {} is not one of the allowed values", path, value));
+        }
+    }
+    if let Some(expected) = map.get("const") {
+        if expected != value {
+            return Err(format!("{}: expected {}", path, expected));
+        }
+    }
+
+    if let Some(type_value) = map.get("type") {
+        let types: Vec<&str> = match type_value {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(value, t)) {
+            return Err(format!("{}: expected type {}", path, types.join(" | ")));
+        }
+    }
+
+    if let Value::Object(obj) = value {
+        for key in map.get("required").and_then(|r| r.as_array()).into_iter().flatten() {
+            if let Some(key) = key.as_str() {
+                if !obj.contains_key(key) {
+                    return Err(format!("{}: missing required property '{}'", path, key));
+                }
+            }
+        }
+
+        let properties = map.get("properties").and_then(|p| p.as_object());
+        for (key, child) in obj {
+            match properties.and_then(|p| p.get(key)) {
+                Some(child_schema) => {
+                    validate_node(child, child_schema, root, &format!("{}.{}", path, key))?;
+                }
+                None => {
+                    if map.get("additionalProperties") == Some(&Value::Bool(false)) {
+                        return Err(format!("{}: unexpected property '{}'", path, key));
+                    }
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, map.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_node(item, item_schema, root, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn matches_type(value: &Value, type_name: &str) -> bool {
+    match type_name.to_lowercase().as_str() {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false)
+        }
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -489,4 +593,24 @@ mod tests {
         assert_eq!(schema["properties"]["name"]["type"], "string");
         assert!(schema["properties"]["name"].get("anyOf").is_none());
     }
+
+    #[test]
+    fn test_validate_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "tags": { "type": "array", "items": { "$ref": "#/$defs/tag" } }
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+            "$defs": { "tag": { "type": "string", "enum": ["a", "b"] } }
+        });
+
+        assert!(validate_against_schema(&json!({ "name": "x", "tags": ["a"] }), &schema).is_ok());
+        assert!(validate_against_schema(&json!({ "tags": [] }), &schema).is_err());
+        assert!(validate_against_schema(&json!({ "name": "x", "tags": ["c"] }), &schema).is_err());
+        assert!(validate_against_schema(&json!({ "name": "x", "extra": 1 }), &schema).is_err());
+        assert!(validate_against_schema(&json!({ "name": 1 }), &schema).is_err());
+    }
 }
@@ -70,6 +70,23 @@ pub fn map_claude_model_to_gemini(input: &str) -> String {
     "claude-sonnet-4-5".to_string()
 }
 
+/// 默认最大输出 token 数 (未知模型)
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 64000;
+
+/// 获取上游模型的最大输出 token 数
+pub fn max_output_tokens_for_model(mapped_model: &str) -> u32 {
+    if mapped_model.starts_with("gemini-2.0") {
+        8192
+    } else if mapped_model.starts_with("gemini-") {
+        // Gemini 2.5 / 3 系列
+        65535
+    } else if mapped_model.starts_with("claude-opus-4-1") || mapped_model == "claude-opus-4" {
+        32000
+    } else {
+        DEFAULT_MAX_OUTPUT_TOKENS
+    }
+}
+
 /// 获取所有内置支持的模型列表关键字
 pub fn get_supported_models() -> Vec<String> {
     CLAUDE_TO_GEMINI.keys().map(|s| s.to_string()).collect()
@@ -132,7 +149,7 @@ pub async fn get_all_dynamic_models(
 /// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
 /// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
 /// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
     if let Some(star_pos) = pattern.find('*') {
         let prefix = &pattern[..star_pos];
         let suffix = &pattern[star_pos + 1..];
@@ -177,10 +194,37 @@ pub fn resolve_model_route(
     result
 }
 
+/// 模型回退链: 主模型之后依次尝试配置的回退模型
+/// 先按客户端请求的模型名查找, 再按映射后的上游模型名查找
+pub fn model_fallback_chain(
+    requested_model: &str,
+    primary_model: &str,
+    fallbacks: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut chain = vec![primary_model.to_string()];
+    let configured = fallbacks
+        .get(requested_model)
+        .or_else(|| fallbacks.get(primary_model));
+    for model in configured.into_iter().flatten() {
+        if !chain.contains(model) {
+            chain.push(model.clone());
+        }
+    }
+    chain
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_max_output_tokens_for_model() {
+        assert_eq!(max_output_tokens_for_model("gemini-2.5-flash"), 65535);
+        assert_eq!(max_output_tokens_for_model("gemini-2.0-flash"), 8192);
+        assert_eq!(max_output_tokens_for_model("claude-sonnet-4-5-thinking"), 64000);
+        assert_eq!(max_output_tokens_for_model("claude-opus-4-1"), 32000);
+    }
+
     #[test]
     fn test_model_mapping() {
         assert_eq!(
@@ -201,4 +245,23 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    #[test]
+    fn test_model_fallback_chain() {
+        let fallbacks = HashMap::from([(
+            "gemini-3-pro-high".to_string(),
+            vec!["gemini-3-pro-low".to_string(), "gemini-2.5-pro".to_string(), "gemini-3-pro-high".to_string()],
+        )]);
+
+        assert_eq!(
+            model_fallback_chain("gemini-3-pro-high", "gemini-3-pro-high", &fallbacks),
+            vec!["gemini-3-pro-high", "gemini-3-pro-low", "gemini-2.5-pro"]
+        );
+        // Aliases resolve through the mapped model
+        assert_eq!(model_fallback_chain("gpt-4o", "gemini-3-pro-high", &fallbacks).len(), 3);
+        assert_eq!(
+            model_fallback_chain("gemini-2.5-flash", "gemini-2.5-flash", &fallbacks),
+            vec!["gemini-2.5-flash"]
+        );
+    }
 }
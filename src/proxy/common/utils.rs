@@ -9,6 +9,15 @@ pub fn generate_random_id() -> String {
         .collect()
 }
 
+/// 短哈希 (SHA-256 前 8 字节, hex), 用于从对话内容派生会话 ID
+pub fn short_hash(text: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(text.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 /// 根据模型名称推测功能类型
 // 注意：此函数已弃用，请改用 mappers::common_utils::resolve_request_config
 pub fn _deprecated_infer_quota_group(model: &str) -> String {
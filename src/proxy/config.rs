@@ -1,10 +1,19 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::account::{ModelRoute, SelectionStrategy};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
     pub port: u16,
     pub api_key: String,
     pub allow_lan_access: bool,
+    /// Per-model fallback chains, see `config::ProxyConfig::model_fallbacks`
+    pub model_fallbacks: HashMap<String, Vec<String>>,
+    /// How accounts are picked, see `config::account::SelectionStrategy`
+    pub selection_strategy: SelectionStrategy,
+    /// Model pattern -> account group rules, see `config::ProxyConfig::model_routes`
+    pub model_routes: Vec<ModelRoute>,
 }
 
 impl Default for ProxyConfig {
@@ -13,6 +22,9 @@ impl Default for ProxyConfig {
             port: 8045,
             api_key: String::new(),
             allow_lan_access: true,
+            model_fallbacks: HashMap::new(),
+            selection_strategy: SelectionStrategy::default(),
+            model_routes: Vec::new(),
         }
     }
 }
@@ -0,0 +1,135 @@
+// Account cooldown table
+//
+// After a 429 / RESOURCE_EXHAUSTED, every request skips the account for the
+// retryDelay / Retry-After the upstream gave, instead of each new request hitting it again.
+// Upstream quotas are per model, so cooldowns are keyed by (account, model): the same
+// account can still serve a fallback model.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Daily quota resets can report very long delays; never park an account longer than this
+const MAX_COOLDOWN: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Cooldown {
+    email: String,
+    model: String,
+    until: SystemTime,
+    reason: String,
+}
+
+/// Status row for `drovity status`
+#[derive(Debug, Clone, Serialize)]
+pub struct CooldownStatus {
+    pub account_id: String,
+    pub email: String,
+    pub model: String,
+    pub remaining_secs: u64,
+    pub reason: String,
+}
+
+/// (account id, model) -> cooldown, shared by all requests
+pub struct CooldownTable {
+    entries: Mutex<HashMap<(String, String), Cooldown>>,
+}
+
+impl CooldownTable {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Park an account for `duration` on one model
+    pub fn set(&self, account_id: &str, email: &str, model: &str, duration: Duration, reason: &str) {
+        let duration = duration.min(MAX_COOLDOWN);
+        if let Ok(mut entries) = self.entries.lock() {
+            tracing::warn!("   ❄️ Cooling down {} on {} for {}s ({})", email, model, duration.as_secs(), reason);
+            entries.insert(
+                (account_id.to_string(), model.to_string()),
+                Cooldown {
+                    email: email.to_string(),
+                    model: model.to_string(),
+                    until: SystemTime::now() + duration,
+                    reason: reason.to_string(),
+                },
+            );
+        }
+    }
+
+    /// Remaining cooldown, `None` once the account has recovered
+    pub fn remaining(&self, account_id: &str, model: &str) -> Option<Duration> {
+        let mut entries = self.entries.lock().ok()?;
+        let key = (account_id.to_string(), model.to_string());
+        let remaining = entries
+            .get(&key)
+            .and_then(|c| c.until.duration_since(SystemTime::now()).ok());
+        if remaining.is_none() {
+            entries.remove(&key);
+        }
+        remaining
+    }
+
+    pub fn is_cooling(&self, account_id: &str, model: &str) -> bool {
+        self.remaining(account_id, model).is_some()
+    }
+
+    /// Accounts still cooling down
+    pub fn snapshot(&self) -> Vec<CooldownStatus> {
+        let now = SystemTime::now();
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries.retain(|_, c| c.until > now);
+
+        let mut rows: Vec<CooldownStatus> = entries
+            .iter()
+            .map(|((id, _), c)| CooldownStatus {
+                account_id: id.clone(),
+                email: c.email.clone(),
+                model: c.model.clone(),
+                remaining_secs: c.until.duration_since(now).map(|d| d.as_secs()).unwrap_or(0),
+                reason: c.reason.clone(),
+            })
+            .collect();
+        rows.sort_by_key(|r| r.remaining_secs);
+        rows
+    }
+}
+
+impl Default for CooldownTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown_table() {
+        let table = CooldownTable::new();
+        assert!(!table.is_cooling("acc-1", "gemini-3-pro-high"));
+
+        table.set("acc-1", "a@example.com", "gemini-3-pro-high", Duration::from_secs(120), "429");
+        assert!(table.is_cooling("acc-1", "gemini-3-pro-high"));
+        assert!(!table.is_cooling("acc-2", "gemini-3-pro-high"));
+        // Other models keep their own quota
+        assert!(!table.is_cooling("acc-1", "gemini-2.5-pro"));
+
+        let rows = table.snapshot();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].email, "a@example.com");
+        assert_eq!(rows[0].model, "gemini-3-pro-high");
+        assert!(rows[0].remaining_secs > 100);
+
+        // Expired entries disappear
+        table.set("acc-1", "a@example.com", "gemini-3-pro-high", Duration::ZERO, "429");
+        assert!(!table.is_cooling("acc-1", "gemini-3-pro-high"));
+        assert!(table.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+// Upstream error classification + client error envelopes
+//
+// Rotation, cooldowns and the status returned to the client are decided from the HTTP
+// status and Google's error.status / ErrorInfo.reason, not by substring-matching the
+// formatted error. Errors sent to clients use the caller's protocol (Anthropic / OpenAI).
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Used when upstream rate-limits without saying for how long
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// What kind of failure upstream reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamErrorKind {
+    /// 429 / RESOURCE_EXHAUSTED: per-minute limits, retry soon on another account
+    RateLimited,
+    /// ErrorInfo.reason QUOTA_EXHAUSTED: the account is out of quota
+    QuotaExhausted,
+    /// 401 / 403: token or project problem on this account
+    Auth,
+    /// 400: the request itself is wrong, other accounts will fail too
+    InvalidRequest,
+    /// 404: unknown model or endpoint
+    NotFound,
+    /// 5xx: transient upstream failure
+    Server,
+    /// Anything else
+    Other,
+}
+
+/// Error response from the v1internal API
+#[derive(Debug, Clone, Error)]
+#[error("Gemini API error {status}: {body}")]
+pub struct UpstreamError {
+    /// HTTP status code
+    pub status: u16,
+    /// Google `error.status`, e.g. `RESOURCE_EXHAUSTED`
+    pub google_status: Option<String>,
+    /// `ErrorInfo.reason`, e.g. `QUOTA_EXHAUSTED`
+    pub reason: Option<String>,
+    /// Google `error.message`
+    pub message: Option<String>,
+    /// Retry hint from `RetryInfo.retryDelay`, else the `Retry-After` header
+    pub retry_delay: Option<Duration>,
+    /// Raw response body
+    pub body: String,
+}
+
+impl UpstreamError {
+    /// Parse a non-2xx upstream response
+    pub fn from_response(status: u16, retry_after: Option<&str>, body: &str) -> Self {
+        let error = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|v| v.get("error").cloned())
+            .unwrap_or(Value::Null);
+        let details = error["details"].as_array().cloned().unwrap_or_default();
+
+        let reason = details
+            .iter()
+            .find_map(|d| d["reason"].as_str())
+            .map(str::to_string);
+        let retry_delay = details
+            .iter()
+            .find_map(|d| d["retryDelay"].as_str())
+            .and_then(parse_duration)
+            .or_else(|| {
+                retry_after
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+            });
+
+        Self {
+            status,
+            google_status: error["status"].as_str().map(str::to_string),
+            reason,
+            message: error["message"].as_str().map(str::to_string),
+            retry_delay,
+            body: body.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> UpstreamErrorKind {
+        if self.reason.as_deref() == Some("QUOTA_EXHAUSTED") {
+            return UpstreamErrorKind::QuotaExhausted;
+        }
+
+        match (self.status, self.google_status.as_deref()) {
+            (429, _) | (_, Some("RESOURCE_EXHAUSTED")) => UpstreamErrorKind::RateLimited,
+            (401 | 403, _) | (_, Some("UNAUTHENTICATED" | "PERMISSION_DENIED")) => UpstreamErrorKind::Auth,
+            (404, _) | (_, Some("NOT_FOUND")) => UpstreamErrorKind::NotFound,
+            (400, _) | (_, Some("INVALID_ARGUMENT" | "FAILED_PRECONDITION")) => {
+                UpstreamErrorKind::InvalidRequest
+            }
+            (500..=599, _) | (_, Some("UNAVAILABLE" | "INTERNAL" | "DEADLINE_EXCEEDED")) => {
+                UpstreamErrorKind::Server
+            }
+            _ => UpstreamErrorKind::Other,
+        }
+    }
+
+    /// Whether retrying on another account can help
+    pub fn should_rotate(&self) -> bool {
+        !matches!(
+            self.kind(),
+            UpstreamErrorKind::InvalidRequest | UpstreamErrorKind::NotFound
+        )
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(
+            self.kind(),
+            UpstreamErrorKind::RateLimited | UpstreamErrorKind::QuotaExhausted
+        )
+    }
+
+    /// Permission denied / not found for the account's Cloud project, i.e. the
+    /// cached project_id is stale (a 404 for an unknown model does not count)
+    pub fn is_project_error(&self) -> bool {
+        let denied_or_missing = matches!(self.status, 403 | 404)
+            || matches!(self.google_status.as_deref(), Some("PERMISSION_DENIED" | "NOT_FOUND"));
+        denied_or_missing
+            && self
+                .message
+                .as_deref()
+                .unwrap_or(&self.body)
+                .to_ascii_lowercase()
+                .contains("project")
+    }
+
+    /// How long to park the account, if at all
+    pub fn cooldown(&self) -> Option<Duration> {
+        self.is_rate_limited()
+            .then(|| self.retry_delay.unwrap_or(DEFAULT_COOLDOWN))
+    }
+
+    /// Status code returned to the client once we stop retrying
+    pub fn client_status(&self) -> StatusCode {
+        match self.kind() {
+            UpstreamErrorKind::InvalidRequest => StatusCode::BAD_REQUEST,
+            UpstreamErrorKind::NotFound => StatusCode::NOT_FOUND,
+            UpstreamErrorKind::RateLimited | UpstreamErrorKind::QuotaExhausted => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            // Our Google account is broken, not the client's credentials
+            UpstreamErrorKind::Auth | UpstreamErrorKind::Server | UpstreamErrorKind::Other => {
+                StatusCode::BAD_GATEWAY
+            }
+        }
+    }
+}
+
+/// Client-facing status for any failed attempt (transport errors count as 502)
+pub fn client_status_for(error: &anyhow::Error) -> StatusCode {
+    error
+        .downcast_ref::<UpstreamError>()
+        .map(UpstreamError::client_status)
+        .unwrap_or(StatusCode::BAD_GATEWAY)
+}
+
+/// Wire protocol of the calling client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientProtocol {
+    /// /v1/chat/completions
+    OpenAI,
+    /// /v1/responses (same error body, different stream events)
+    Responses,
+    /// /v1/messages
+    Anthropic,
+}
+
+impl ClientProtocol {
+    /// Anthropic `error.type` / OpenAI `error.type` and `error.code` for a status
+    fn error_type(self, status: StatusCode) -> (&'static str, Option<&'static str>) {
+        match self {
+            ClientProtocol::Anthropic => {
+                let error_type = match status.as_u16() {
+                    400 | 422 => "invalid_request_error",
+                    401 => "authentication_error",
+                    403 => "permission_error",
+                    404 => "not_found_error",
+                    413 => "request_too_large",
+                    429 => "rate_limit_error",
+                    503 | 529 => "overloaded_error",
+                    _ => "api_error",
+                };
+                (error_type, None)
+            }
+            ClientProtocol::OpenAI | ClientProtocol::Responses => match status.as_u16() {
+                400 | 422 => ("invalid_request_error", None),
+                401 => ("authentication_error", Some("invalid_api_key")),
+                403 => ("permission_error", None),
+                404 => ("invalid_request_error", Some("not_found")),
+                429 => ("rate_limit_error", Some("rate_limit_exceeded")),
+                503 => ("server_error", Some("service_unavailable")),
+                _ => ("server_error", None),
+            },
+        }
+    }
+
+    /// Error body in the shape this protocol's clients parse
+    pub fn error_body(self, status: StatusCode, message: &str) -> Value {
+        let (error_type, code) = self.error_type(status);
+        match self {
+            ClientProtocol::Anthropic => json!({
+                "type": "error",
+                "error": { "type": error_type, "message": message }
+            }),
+            ClientProtocol::OpenAI | ClientProtocol::Responses => json!({
+                "error": { "message": message, "type": error_type, "param": null, "code": code }
+            }),
+        }
+    }
+
+    pub fn error_response(self, status: StatusCode, message: &str) -> Response {
+        (status, Json(self.error_body(status, message))).into_response()
+    }
+
+    /// SSE event for failures after the stream has started (headers already sent)
+    pub fn sse_error_event(self, message: &str) -> Bytes {
+        let status = StatusCode::BAD_GATEWAY;
+        let body = self.error_body(status, message);
+        let sse = match self {
+            ClientProtocol::Anthropic => format!("event: error\ndata: {}\n\n", body),
+            // chat.completions streams carry the error object in a plain data line
+            ClientProtocol::OpenAI => format!("data: {}\n\n", body),
+            ClientProtocol::Responses => {
+                let (_, code) = self.error_type(status);
+                let event = json!({
+                    "type": "error",
+                    "code": code.unwrap_or("server_error"),
+                    "message": message,
+                    "param": null
+                });
+                format!("event: error\ndata: {}\n\n", event)
+            }
+        };
+        Bytes::from(sse)
+    }
+}
+
+/// Google duration string, e.g. `"12.5s"`
+fn parse_duration(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .strip_suffix('s')?
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn google_error(code: u16, status: &str, details: &str) -> String {
+        format!(
+            r#"{{"error":{{"code":{},"message":"boom","status":"{}","details":[{}]}}}}"#,
+            code, status, details
+        )
+    }
+
+    #[test]
+    fn test_classification_table() {
+        let cases = [
+            (429, google_error(429, "RESOURCE_EXHAUSTED", ""), UpstreamErrorKind::RateLimited, true),
+            (
+                429,
+                google_error(429, "RESOURCE_EXHAUSTED", r#"{"@type":"type.googleapis.com/google.rpc.ErrorInfo","reason":"QUOTA_EXHAUSTED"}"#),
+                UpstreamErrorKind::QuotaExhausted,
+                true,
+            ),
+            (401, google_error(401, "UNAUTHENTICATED", ""), UpstreamErrorKind::Auth, true),
+            (403, google_error(403, "PERMISSION_DENIED", ""), UpstreamErrorKind::Auth, true),
+            (400, google_error(400, "INVALID_ARGUMENT", ""), UpstreamErrorKind::InvalidRequest, false),
+            (404, google_error(404, "NOT_FOUND", ""), UpstreamErrorKind::NotFound, false),
+            (503, google_error(503, "UNAVAILABLE", ""), UpstreamErrorKind::Server, true),
+            (500, "Internal error".to_string(), UpstreamErrorKind::Server, true),
+            (418, "teapot".to_string(), UpstreamErrorKind::Other, true),
+        ];
+
+        for (status, body, kind, rotate) in cases {
+            let error = UpstreamError::from_response(status, None, &body);
+            assert_eq!(error.kind(), kind, "status {} body {}", status, body);
+            assert_eq!(error.should_rotate(), rotate, "status {}", status);
+        }
+    }
+
+    #[test]
+    fn test_numbers_in_body_do_not_affect_classification() {
+        // A 400 whose message mentions 429 and 503 is still a bad request
+        let body = google_error(400, "INVALID_ARGUMENT", "").replace("boom", "prompt mentions 429 and 503");
+        let error = UpstreamError::from_response(400, None, &body);
+        assert_eq!(error.kind(), UpstreamErrorKind::InvalidRequest);
+        assert_eq!(error.client_status(), StatusCode::BAD_REQUEST);
+        assert!(error.cooldown().is_none());
+
+        // And a 503 mentioning 400 is still retryable
+        let error = UpstreamError::from_response(503, None, "model overloaded, code 400 fallback");
+        assert!(error.should_rotate());
+    }
+
+    #[test]
+    fn test_project_errors() {
+        let error = |status: u16, google_status: &str, message: &str| {
+            let body = json!({ "error": { "code": status, "status": google_status, "message": message } });
+            UpstreamError::from_response(status, None, &body.to_string())
+        };
+
+        assert!(error(403, "PERMISSION_DENIED", "Permission denied on resource project demo-123").is_project_error());
+        assert!(error(404, "NOT_FOUND", "Project 'demo-123' not found").is_project_error());
+        assert!(!error(404, "NOT_FOUND", "Model gemini-9 not found").is_project_error());
+        assert!(!error(400, "INVALID_ARGUMENT", "Invalid project field").is_project_error());
+        assert!(!error(503, "UNAVAILABLE", "Backend error").is_project_error());
+    }
+
+    #[test]
+    fn test_retry_hints() {
+        let body = google_error(
+            429,
+            "RESOURCE_EXHAUSTED",
+            r#"{"@type":"type.googleapis.com/google.rpc.RetryInfo","retryDelay":"12.5s"}"#,
+        );
+        let error = UpstreamError::from_response(429, Some("30"), &body);
+        assert_eq!(error.retry_delay, Some(Duration::from_millis(12_500)));
+        assert_eq!(error.cooldown(), Some(Duration::from_millis(12_500)));
+
+        // Retry-After header when the body has no RetryInfo
+        let error = UpstreamError::from_response(429, Some("30"), "rate limited");
+        assert_eq!(error.cooldown(), Some(Duration::from_secs(30)));
+
+        let error = UpstreamError::from_response(429, None, "rate limited");
+        assert_eq!(error.cooldown(), Some(DEFAULT_COOLDOWN));
+        assert_eq!(error.client_status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn test_error_envelopes() {
+        let body = ClientProtocol::Anthropic.error_body(StatusCode::SERVICE_UNAVAILABLE, "No accounts available");
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "overloaded_error");
+        assert_eq!(body["error"]["message"], "No accounts available");
+
+        let body = ClientProtocol::OpenAI.error_body(StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+        assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+        assert_eq!(body["error"]["message"], "slow down");
+
+        let event = ClientProtocol::Anthropic.sse_error_event("upstream closed");
+        let event = std::str::from_utf8(&event).unwrap();
+        assert!(event.starts_with("event: error\n"));
+        assert!(event.contains(r#""type":"api_error""#));
+
+        let event = ClientProtocol::Responses.sse_error_event("upstream closed");
+        assert!(std::str::from_utf8(&event).unwrap().contains(r#""type":"error""#));
+
+        // Upstream auth failures are a gateway problem, not the client's key
+        let auth = UpstreamError::from_response(401, None, "unauthenticated");
+        assert_eq!(auth.client_status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -1,66 +1,105 @@
-// Global thought_signature storage shared by all endpoints
+// Session-scoped thought_signature storage shared by all endpoints
 // Used to capture and replay signatures for Gemini 3+ function calls when clients don't pass them back.
+// Keyed by conversation so several sessions sharing one daemon never see each other's signatures.
 
+use std::collections::HashMap;
 use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+// Same lifetime as SignatureCache entries
+const SESSION_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+const MAX_SESSIONS: usize = 1000;
 
-fn get_thought_sig_storage() -> &'static Mutex<Option<String>> {
-    GLOBAL_THOUGHT_SIG.get_or_init(|| Mutex::new(None))
+struct SessionEntry {
+    signature: String,
+    last_access: Instant,
 }
 
-/// Store thought_signature to global storage.
-/// Only stores if the new signature is longer than the existing one,
-/// to avoid short/partial signatures overwriting valid ones.
-#[allow(dead_code)]
-pub fn store_thought_signature(sig: &str) {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        let should_store = match &*guard {
-            None => true,
-            Some(existing) => sig.len() > existing.len(),
+impl SessionEntry {
+    fn is_expired(&self) -> bool {
+        self.last_access.elapsed() > SESSION_TTL
+    }
+}
+
+static SESSION_THOUGHT_SIGS: OnceLock<Mutex<HashMap<String, SessionEntry>>> = OnceLock::new();
+
+fn get_thought_sig_storage() -> &'static Mutex<HashMap<String, SessionEntry>> {
+    SESSION_THOUGHT_SIGS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Drop expired sessions, then the least recently used ones above the cap.
+fn evict(sessions: &mut HashMap<String, SessionEntry>) {
+    sessions.retain(|_, entry| !entry.is_expired());
+
+    while sessions.len() > MAX_SESSIONS {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_access)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => sessions.remove(&key),
+            None => break,
         };
+    }
+}
 
-        if should_store {
-            tracing::debug!(
-                "[ThoughtSig] Storing new signature (length: {}, replacing old length: {:?})",
-                sig.len(),
-                guard.as_ref().map(|s| s.len())
-            );
-            *guard = Some(sig.to_string());
-        } else {
-            tracing::debug!(
-                "[ThoughtSig] Skipping shorter signature (new length: {}, existing length: {})",
-                sig.len(),
-                guard.as_ref().map(|s| s.len()).unwrap_or(0)
-            );
+/// Store thought_signature for a session.
+/// Only stores if the new signature is longer than the existing one,
+/// to avoid short/partial signatures overwriting valid ones.
+pub fn store_thought_signature(session_id: &str, sig: &str) {
+    if let Ok(mut sessions) = get_thought_sig_storage().lock() {
+        if let Some(entry) = sessions.get_mut(session_id).filter(|e| !e.is_expired()) {
+            entry.last_access = Instant::now();
+            if sig.len() <= entry.signature.len() {
+                tracing::debug!(
+                    "[ThoughtSig] Skipping shorter signature for session {} (new length: {}, existing length: {})",
+                    session_id,
+                    sig.len(),
+                    entry.signature.len()
+                );
+                return;
+            }
         }
+
+        tracing::debug!(
+            "[ThoughtSig] Storing new signature for session {} (length: {})",
+            session_id,
+            sig.len()
+        );
+        sessions.insert(
+            session_id.to_string(),
+            SessionEntry {
+                signature: sig.to_string(),
+                last_access: Instant::now(),
+            },
+        );
+        evict(&mut sessions);
     }
 }
 
-/// Get the stored thought_signature without clearing it.
-pub fn get_thought_signature() -> Option<String> {
-    if let Ok(guard) = get_thought_sig_storage().lock() {
-        guard.clone()
-    } else {
-        None
-    }
+/// Get the stored thought_signature of a session without clearing it.
+pub fn get_thought_signature(session_id: &str) -> Option<String> {
+    let mut sessions = get_thought_sig_storage().lock().ok()?;
+    let entry = sessions.get_mut(session_id).filter(|e| !e.is_expired())?;
+    entry.last_access = Instant::now();
+    Some(entry.signature.clone())
 }
 
-/// Get and clear the stored thought_signature.
+/// Get and clear the stored thought_signature of a session.
 #[allow(dead_code)]
-pub fn take_thought_signature() -> Option<String> {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        guard.take()
-    } else {
-        None
-    }
+pub fn take_thought_signature(session_id: &str) -> Option<String> {
+    let mut sessions = get_thought_sig_storage().lock().ok()?;
+    sessions
+        .remove(session_id)
+        .filter(|e| !e.is_expired())
+        .map(|e| e.signature)
 }
 
-/// Clear the stored thought_signature.
+/// Clear the stored thought_signature of a session.
 #[allow(dead_code)]
-pub fn clear_thought_signature() {
-    if let Ok(mut guard) = get_thought_sig_storage().lock() {
-        *guard = None;
+pub fn clear_thought_signature(session_id: &str) {
+    if let Ok(mut sessions) = get_thought_sig_storage().lock() {
+        sessions.remove(session_id);
     }
 }
 
@@ -70,39 +109,71 @@ mod tests {
 
     #[test]
     fn test_signature_storage() {
+        let session = "test-session-storage";
         // Clear any existing state
-        clear_thought_signature();
+        clear_thought_signature(session);
 
         // Should be empty initially
-        assert!(get_thought_signature().is_none());
+        assert!(get_thought_signature(session).is_none());
 
         // Store a signature
-        store_thought_signature("test_signature_1234");
+        store_thought_signature(session, "test_signature_1234");
         assert_eq!(
-            get_thought_signature(),
+            get_thought_signature(session),
             Some("test_signature_1234".to_string())
         );
 
         // Shorter signature should NOT overwrite
-        store_thought_signature("short");
+        store_thought_signature(session, "short");
         assert_eq!(
-            get_thought_signature(),
+            get_thought_signature(session),
             Some("test_signature_1234".to_string())
         );
 
         // Longer signature SHOULD overwrite
-        store_thought_signature("test_signature_1234_longer_version");
+        store_thought_signature(session, "test_signature_1234_longer_version");
         assert_eq!(
-            get_thought_signature(),
+            get_thought_signature(session),
             Some("test_signature_1234_longer_version".to_string())
         );
 
         // Take should clear
-        let taken = take_thought_signature();
+        let taken = take_thought_signature(session);
         assert_eq!(
             taken,
             Some("test_signature_1234_longer_version".to_string())
         );
-        assert!(get_thought_signature().is_none());
+        assert!(get_thought_signature(session).is_none());
+    }
+
+    #[test]
+    fn test_sessions_are_isolated() {
+        store_thought_signature("test-session-a", "signature_of_session_a");
+        assert!(get_thought_signature("test-session-b").is_none());
+        assert_eq!(
+            get_thought_signature("test-session-a"),
+            Some("signature_of_session_a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let now = Instant::now();
+        let mut sessions: HashMap<String, SessionEntry> = (0..=MAX_SESSIONS)
+            .map(|i| {
+                (
+                    format!("session-{}", i),
+                    SessionEntry {
+                        signature: "sig".to_string(),
+                        last_access: now + Duration::from_millis(i as u64),
+                    },
+                )
+            })
+            .collect();
+
+        evict(&mut sessions);
+        assert_eq!(sessions.len(), MAX_SESSIONS);
+        assert!(!sessions.contains_key("session-0"));
+        assert!(sessions.contains_key(&format!("session-{}", MAX_SESSIONS)));
     }
 }
@@ -1,11 +1,17 @@
+pub mod auth;
 pub mod config;
 pub mod server;
 pub mod project_resolver;
 pub mod claude_converter;
 pub mod claude;
+pub mod openai;
 pub mod common;
 pub mod mappers;
 pub mod signature_cache;
+pub mod session_affinity;
+pub mod cooldown;
+pub mod error;
+pub mod account_pool;
 
 pub use server::start_server;
 pub use signature_cache::SignatureCache;
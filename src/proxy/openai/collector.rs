@@ -0,0 +1,230 @@
+// Stream 收集器 - 将 chat.completion.chunk 流转换为完整的 chat.completion
+// 用于非 Stream 请求的自动转换
+
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+
+/// 单个 choice 的累积状态
+#[derive(Default)]
+struct ChoiceAccumulator {
+    content: String,
+    reasoning_content: String,
+    /// tool_calls[].index -> (id, name, arguments)
+    tool_calls: BTreeMap<u64, (String, String, String)>,
+    logprobs: Option<Vec<Value>>,
+    finish_reason: Option<String>,
+}
+
+/// Check every choice of a collected chat.completion against the requested output schema
+pub fn validate_structured_output(response: &Value, schema: &Value) -> Result<(), String> {
+    for choice in response["choices"].as_array().into_iter().flatten() {
+        // Tool calls are not subject to response_format
+        if choice["message"]["tool_calls"].is_array() {
+            continue;
+        }
+        let content = choice["message"]["content"].as_str().unwrap_or_default();
+        let parsed: Value = serde_json::from_str(content.trim())
+            .map_err(|e| format!("Output is not valid JSON: {}", e))?;
+        crate::proxy::common::json_schema::validate_against_schema(&parsed, schema)?;
+    }
+    Ok(())
+}
+
+/// 将 OpenAI SSE Stream 收集为完整的 chat.completion 响应
+pub async fn collect_stream_to_json<S>(mut stream: S) -> Result<Value, String>
+where
+    S: futures::Stream<Item = Result<Bytes, io::Error>> + Unpin,
+{
+    let mut id = String::new();
+    let mut created = chrono::Utc::now().timestamp();
+    let mut model = String::new();
+    let mut usage: Option<Value> = None;
+    let mut choices: BTreeMap<u64, ChoiceAccumulator> = BTreeMap::new();
+    let mut buffer = String::new();
+
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = chunk_result.map_err(|e| format!("Stream error: {}", e))?;
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(pos) = buffer.find('\n') {
+            let line: String = buffer.drain(..=pos).collect();
+            let data = match line.trim().strip_prefix("data:") {
+                Some(d) => d.trim(),
+                None => continue,
+            };
+            if data.is_empty() || data == "[DONE]" {
+                continue;
+            }
+
+            let event: Value = match serde_json::from_str(data) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            if let Some(error) = event.get("error") {
+                return Err(format!("Stream error: {}", error));
+            }
+
+            if let Some(v) = event.get("id").and_then(|v| v.as_str()) {
+                id = v.to_string();
+            }
+            if let Some(v) = event.get("created").and_then(|v| v.as_i64()) {
+                created = v;
+            }
+            if let Some(v) = event.get("model").and_then(|v| v.as_str()) {
+                model = v.to_string();
+            }
+            if let Some(u) = event.get("usage").filter(|u| !u.is_null()) {
+                usage = Some(u.clone());
+            }
+
+            for choice in event.get("choices").and_then(|c| c.as_array()).into_iter().flatten() {
+                let index = choice.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                let acc = choices.entry(index).or_default();
+                let delta = &choice["delta"];
+
+                if let Some(text) = delta.get("content").and_then(|v| v.as_str()) {
+                    acc.content.push_str(text);
+                }
+                if let Some(text) = delta.get("reasoning_content").and_then(|v| v.as_str()) {
+                    acc.reasoning_content.push_str(text);
+                }
+                for call in delta.get("tool_calls").and_then(|v| v.as_array()).into_iter().flatten() {
+                    let call_index = call.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                    let entry = acc.tool_calls.entry(call_index).or_default();
+                    if let Some(id) = call.get("id").and_then(|v| v.as_str()) {
+                        entry.0 = id.to_string();
+                    }
+                    if let Some(name) = call["function"].get("name").and_then(|v| v.as_str()) {
+                        entry.1 = name.to_string();
+                    }
+                    if let Some(args) = call["function"].get("arguments").and_then(|v| v.as_str()) {
+                        entry.2.push_str(args);
+                    }
+                }
+                if let Some(content) = choice["logprobs"].get("content").and_then(|c| c.as_array()) {
+                    acc.logprobs.get_or_insert_with(Vec::new).extend(content.iter().cloned());
+                }
+                if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
+                    acc.finish_reason = Some(reason.to_string());
+                }
+            }
+        }
+    }
+
+    if choices.is_empty() {
+        choices.insert(0, ChoiceAccumulator::default());
+    }
+
+    let choices: Vec<Value> = choices
+        .into_iter()
+        .map(|(index, acc)| {
+            let mut message = json!({
+                "role": "assistant",
+                "content": acc.content,
+            });
+            if !acc.reasoning_content.is_empty() {
+                message["reasoning_content"] = json!(acc.reasoning_content);
+            }
+            if !acc.tool_calls.is_empty() {
+                if acc.content.is_empty() {
+                    message["content"] = Value::Null;
+                }
+                message["tool_calls"] = acc
+                    .tool_calls
+                    .into_values()
+                    .map(|(id, name, arguments)| {
+                        json!({
+                            "id": id,
+                            "type": "function",
+                            "function": { "name": name, "arguments": arguments }
+                        })
+                    })
+                    .collect();
+            }
+            json!({
+                "index": index,
+                "message": message,
+                "logprobs": acc.logprobs.map(|content| json!({ "content": content })),
+                "finish_reason": acc.finish_reason.unwrap_or_else(|| "stop".to_string())
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "id": if id.is_empty() { format!("chatcmpl-{}", uuid::Uuid::new_v4()) } else { id },
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": choices,
+        "usage": usage.unwrap_or_else(|| json!({
+            "prompt_tokens": 0,
+            "completion_tokens": 0,
+            "total_tokens": 0
+        }))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn test_collect_chunks() {
+        let sse_data = vec![
+            "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"gemini-2.5-flash\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hello\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"gemini-2.5-flash\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\" World\"},",
+            "\"finish_reason\":null}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"gemini-2.5-flash\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"length\"}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"gemini-2.5-flash\",\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,\"total_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        ];
+
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_stream_to_json(byte_stream).await.unwrap();
+        assert_eq!(response["id"], "chatcmpl-1");
+        assert_eq!(response["object"], "chat.completion");
+        assert_eq!(response["choices"][0]["message"]["content"], "Hello World");
+        assert_eq!(response["choices"][0]["finish_reason"], "length");
+        assert_eq!(response["usage"]["total_tokens"], 5);
+    }
+
+    #[test]
+    fn test_validate_structured_output() {
+        let schema = json!({ "type": "object", "required": ["ok"] });
+        let valid = json!({ "choices": [{ "message": { "content": "{\"ok\": true}" } }] });
+        let invalid = json!({ "choices": [{ "message": { "content": "{\"nope\": 1}" } }] });
+        let not_json = json!({ "choices": [{ "message": { "content": "Sure! Here it is" } }] });
+
+        assert!(validate_structured_output(&valid, &schema).is_ok());
+        assert!(validate_structured_output(&invalid, &schema).is_err());
+        assert!(validate_structured_output(&not_json, &json!({})).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_collect_tool_calls() {
+        let sse_data = vec![
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"read\",\"arguments\":\"{\\\"path\\\":\\\"a\\\"}\"}}]},\"finish_reason\":null}]}\n\n",
+            "data: {\"id\":\"chatcmpl-2\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ];
+
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_stream_to_json(byte_stream).await.unwrap();
+        let message = &response["choices"][0]["message"];
+        assert!(message["content"].is_null());
+        assert_eq!(message["tool_calls"][0]["id"], "call_1");
+        assert_eq!(message["tool_calls"][0]["function"]["arguments"], "{\"path\":\"a\"}");
+        assert_eq!(response["choices"][0]["finish_reason"], "tool_calls");
+    }
+}
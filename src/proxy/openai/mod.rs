@@ -0,0 +1,146 @@
+// OpenAI mapper 模块
+// 负责 OpenAI chat.completions / responses ↔ Gemini 协议转换
+
+pub mod collector;
+pub mod request;
+pub mod responses;
+pub mod streaming;
+
+pub use collector::{collect_stream_to_json, validate_structured_output};
+pub use request::{
+    expected_output_schema, resolve_session_id, transform_openai_request, validate_request_params,
+};
+pub use streaming::OpenAIStreamingState;
+
+use crate::proxy::error::ClientProtocol;
+use bytes::Bytes;
+use futures::Stream;
+use std::pin::Pin;
+
+/// 创建从 Gemini SSE 流到 OpenAI chat.completion.chunk 流的转换
+pub fn create_openai_sse_stream(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+    email: String,
+    model: String,
+    include_usage: bool,
+    parallel_tool_calls: bool,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    use async_stream::stream;
+    use bytes::BytesMut;
+    use futures::StreamExt;
+
+    Box::pin(stream! {
+        let mut state = OpenAIStreamingState::new(&model, include_usage)
+            .with_parallel_tool_calls(parallel_tool_calls);
+        let mut buffer = BytesMut::new();
+
+        while let Some(chunk_result) = gemini_stream.next().await {
+            match chunk_result {
+                Ok(chunk) => {
+                    buffer.extend_from_slice(&chunk);
+
+                    // Process complete lines
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line_raw = buffer.split_to(pos + 1);
+                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
+                            let line = line_str.trim();
+                            if line.is_empty() { continue; }
+
+                            for sse_chunk in process_sse_line(line, &mut state, &trace_id, &email) {
+                                yield Ok(sse_chunk);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Headers are already sent: report in-band and skip the normal termination
+                    yield Ok(ClientProtocol::OpenAI.sse_error_event(&format!("Upstream stream error: {}", e)));
+                    return;
+                }
+            }
+        }
+
+        // Ensure termination events are sent
+        for chunk in state.emit_finish() {
+            yield Ok(chunk);
+        }
+    })
+}
+
+/// 处理单行 SSE 数据
+fn process_sse_line(
+    line: &str,
+    state: &mut OpenAIStreamingState,
+    trace_id: &str,
+    email: &str,
+) -> Vec<Bytes> {
+    let data_str = match line.strip_prefix("data:") {
+        Some(d) => d.trim(),
+        None => return vec![],
+    };
+
+    if data_str.is_empty() {
+        return vec![];
+    }
+
+    if data_str == "[DONE]" {
+        return state.emit_finish();
+    }
+
+    let json_value: serde_json::Value = match serde_json::from_str(data_str) {
+        Ok(v) => v,
+        Err(_) => return vec![],
+    };
+
+    // 解包 response 字段 (如果存在)
+    let raw_json = json_value.get("response").unwrap_or(&json_value);
+
+    if let Some(usage) = raw_json.get("usageMetadata") {
+        if raw_json
+            .get("candidates")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("finishReason"))
+            .is_some()
+        {
+            tracing::info!(
+                "[{}] ✓ Stream completed | Account: {} | In: {} tokens | Out: {} tokens",
+                trace_id,
+                email,
+                usage.get("promptTokenCount").and_then(|v| v.as_u64()).unwrap_or(0),
+                usage.get("candidatesTokenCount").and_then(|v| v.as_u64()).unwrap_or(0),
+            );
+        }
+    }
+
+    state.process_gemini_chunk(raw_json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_sse_line_done() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-flash", false);
+        let chunks = process_sse_line("data: [DONE]", &mut state, "test_id", "test@example.com");
+        let all_text: String = chunks
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+        assert!(all_text.contains("[DONE]"));
+        assert!(all_text.contains(r#""finish_reason":"stop""#));
+
+        // Second termination must not emit another [DONE]
+        assert!(state.emit_finish().is_empty());
+    }
+
+    #[test]
+    fn test_process_sse_line_unwraps_envelope() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-flash", false);
+        let line = r#"data: {"response":{"candidates":[{"content":{"parts":[{"text":"Hello"}]}}]}}"#;
+        let chunks = process_sse_line(line, &mut state, "test_id", "test@example.com");
+        assert_eq!(chunks.len(), 1);
+        assert!(String::from_utf8(chunks[0].to_vec()).unwrap().contains("Hello"));
+    }
+}
@@ -0,0 +1,840 @@
+// OpenAI 请求转换 (chat.completions → Gemini v1internal)
+
+use crate::proxy::claude::request::merge_adjacent_roles;
+use crate::proxy::common::json_schema::clean_json_schema;
+use crate::proxy::SignatureCache;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 转换 OpenAI chat.completions 请求为 Gemini v1internal 格式
+pub fn transform_openai_request(
+    payload: &Value,
+    mapped_model: &str,
+    project_id: &str,
+) -> Result<Value, String> {
+    validate_request_params(payload)?;
+
+    let messages = payload["messages"]
+        .as_array()
+        .ok_or_else(|| "Missing messages field".to_string())?;
+
+    let contents = build_contents(messages)?;
+
+    // Extract system message for systemInstruction
+    let system_text: Vec<String> = messages
+        .iter()
+        .filter(|msg| matches!(msg["role"].as_str(), Some("system") | Some("developer")))
+        .map(|msg| content_to_text(&msg["content"]))
+        .filter(|text| !text.is_empty())
+        .collect();
+
+    let mut inner_request = json!({
+        "contents": contents,
+        "generationConfig": build_generation_config(payload, mapped_model)?,
+        "systemInstruction": if !system_text.is_empty() {
+            json!({
+                "role": "user",
+                "parts": system_text.iter().map(|s| json!({"text": s})).collect::<Vec<_>>()
+            })
+        } else {
+            json!(null)
+        },
+        "safetySettings": [
+            { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
+            { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
+            { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
+            { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
+            { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" }
+        ]
+    });
+
+    // Legacy `functions` is the pre-tools spelling of the same thing
+    let legacy_tools = payload.get("functions").and_then(|f| f.as_array()).map(|functions| {
+        json!(functions
+            .iter()
+            .map(|f| json!({ "type": "function", "function": f }))
+            .collect::<Vec<_>>())
+    });
+    let tool_choice = match (payload.get("tool_choice"), payload.get("function_call")) {
+        (Some(choice), _) if !choice.is_null() => Some(choice.clone()),
+        (_, Some(call)) if !call.is_null() => Some(legacy_function_call_to_tool_choice(call)),
+        _ => None,
+    };
+    if let Some(tools) = build_tools(payload.get("tools").or(legacy_tools.as_ref()))? {
+        inner_request["tools"] = tools;
+        inner_request["toolConfig"] = build_tool_config(tool_choice.as_ref())?;
+    }
+
+    // Wrap in v1internal envelope format (like DroidGravity-Manager)
+    Ok(json!({
+        "project": project_id,
+        "requestId": format!("drovity-{}", uuid::Uuid::new_v4()),
+        "request": inner_request,
+        "model": mapped_model,
+        "userAgent": "antigravity",
+        "requestType": "agent"  // CRITICAL: Must be "agent" not "text" for proper quota!
+    }))
+}
+
+/// Parameters that are accepted but do not affect the upstream request
+const PASSTHROUGH_PARAMS: &[&str] = &["model", "messages", "stream", "stream_options", "user", "metadata", "store"];
+
+/// Parameters mapped onto the Gemini request
+const MAPPED_PARAMS: &[&str] = &[
+    "max_tokens",
+    "max_completion_tokens",
+    "temperature",
+    "top_p",
+    "stop",
+    "n",
+    "seed",
+    "presence_penalty",
+    "frequency_penalty",
+    "logprobs",
+    "top_logprobs",
+    "reasoning_effort",
+    "tools",
+    "tool_choice",
+    "functions",
+    "function_call",
+    "parallel_tool_calls",
+    "response_format",
+];
+
+/// Reject parameters we cannot honour instead of silently dropping them
+pub fn validate_request_params(payload: &Value) -> Result<(), String> {
+    let obj = payload
+        .as_object()
+        .ok_or_else(|| "Request body must be a JSON object".to_string())?;
+
+    for (key, value) in obj {
+        if value.is_null() || PASSTHROUGH_PARAMS.contains(&key.as_str()) {
+            continue;
+        }
+        if !MAPPED_PARAMS.contains(&key.as_str()) {
+            return Err(format!("Unsupported parameter: '{}' is not supported by this proxy", key));
+        }
+    }
+
+    Ok(())
+}
+
+/// 构建 generationConfig (OpenAI sampling params → Gemini)
+fn build_generation_config(payload: &Value, mapped_model: &str) -> Result<Value, String> {
+    let number = |key: &str| -> Result<Option<f64>, String> {
+        match payload.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v
+                .as_f64()
+                .map(Some)
+                .ok_or_else(|| format!("Invalid '{}': expected a number", key)),
+        }
+    };
+    let integer = |key: &str| -> Result<Option<i64>, String> {
+        match payload.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v
+                .as_i64()
+                .map(Some)
+                .ok_or_else(|| format!("Invalid '{}': expected an integer", key)),
+        }
+    };
+
+    // max_completion_tokens supersedes the deprecated max_tokens
+    let max_tokens = match integer("max_completion_tokens")? {
+        Some(v) => Some(("max_completion_tokens", v)),
+        None => integer("max_tokens")?.map(|v| ("max_tokens", v)),
+    };
+    if let Some((key, value)) = max_tokens.filter(|&(_, v)| v < 1) {
+        return Err(format!("Invalid '{}': must be at least 1, got {}", key, value));
+    }
+    let max_tokens = max_tokens.map(|(_, v)| v);
+    // Clamped to the model's output limit, like the Claude endpoint
+    let model_limit = i64::from(crate::proxy::common::model_mapping::max_output_tokens_for_model(mapped_model));
+    if max_tokens.is_some_and(|t| t > model_limit) {
+        tracing::debug!(
+            "[OpenAI-Request] max_tokens {:?} clamped to {} for {}",
+            max_tokens,
+            model_limit,
+            mapped_model
+        );
+    }
+    let mut config = json!({ "maxOutputTokens": max_tokens.map_or(model_limit, |t| t.min(model_limit)) });
+
+    if let Some(temperature) = number("temperature")? {
+        config["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = number("top_p")? {
+        config["topP"] = json!(top_p);
+    }
+    if let Some(presence_penalty) = number("presence_penalty")? {
+        config["presencePenalty"] = json!(presence_penalty);
+    }
+    if let Some(frequency_penalty) = number("frequency_penalty")? {
+        config["frequencyPenalty"] = json!(frequency_penalty);
+    }
+    if let Some(seed) = integer("seed")? {
+        config["seed"] = json!(seed);
+    }
+    if let Some(n) = integer("n")? {
+        if n < 1 {
+            return Err("Invalid 'n': must be at least 1".to_string());
+        }
+        config["candidateCount"] = json!(n);
+    }
+
+    match payload.get("stop") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(stop)) => config["stopSequences"] = json!([stop]),
+        Some(Value::Array(stops)) => {
+            if stops.iter().any(|s| !s.is_string()) {
+                return Err("Invalid 'stop': expected a string or an array of strings".to_string());
+            }
+            if !stops.is_empty() {
+                config["stopSequences"] = json!(stops);
+            }
+        }
+        Some(_) => return Err("Invalid 'stop': expected a string or an array of strings".to_string()),
+    }
+
+    if payload.get("logprobs").and_then(|v| v.as_bool()).unwrap_or(false) {
+        config["responseLogprobs"] = json!(true);
+        if let Some(top_logprobs) = integer("top_logprobs")? {
+            config["logprobs"] = json!(top_logprobs);
+        }
+    } else if integer("top_logprobs")?.is_some() {
+        return Err("Invalid 'top_logprobs': requires 'logprobs: true'".to_string());
+    }
+
+    if let Some(effort) = payload.get("reasoning_effort").and_then(|v| v.as_str()) {
+        config["thinkingConfig"] = build_thinking_config(effort, mapped_model)?;
+    }
+
+    if let Some(schema) = expected_output_schema(payload)? {
+        config["responseMimeType"] = json!("application/json");
+        // An empty schema means json_object: any JSON value, no responseSchema
+        if schema.as_object().map(|o| !o.is_empty()).unwrap_or(true) {
+            let mut response_schema = schema;
+            clean_json_schema(&mut response_schema);
+            config["responseSchema"] = response_schema;
+        }
+    }
+
+    Ok(config)
+}
+
+/// 解析会话 ID (用于账号亲和)
+///
+/// Priority: explicit `X-Drovity-Session` header, then `user` / `metadata.user_id`,
+/// then a hash of the first user message (stable across the whole conversation).
+pub fn resolve_session_id(payload: &Value, header: Option<&str>) -> String {
+    if let Some(session) = header.map(str::trim).filter(|h| !h.is_empty()) {
+        return format!("header:{}", session);
+    }
+
+    if let Some(user) = payload["user"]
+        .as_str()
+        .or_else(|| payload["metadata"]["user_id"].as_str())
+        .filter(|u| !u.is_empty())
+    {
+        return format!("user:{}", user);
+    }
+
+    let first_turn = payload["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().find(|m| m["role"] == "user"))
+        .map(|m| match &m["content"] {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        })
+        .unwrap_or_default();
+    format!("turn:{}", crate::proxy::common::utils::short_hash(&first_turn))
+}
+
+/// Schema the final output must satisfy, from `response_format`
+///
+/// Returns `{}` for `json_object` (any JSON) and `None` for plain text.
+pub fn expected_output_schema(payload: &Value) -> Result<Option<Value>, String> {
+    let format = match payload.get("response_format") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(f) => f,
+    };
+
+    match format["type"].as_str() {
+        Some("text") => Ok(None),
+        Some("json_object") => Ok(Some(json!({}))),
+        Some("json_schema") => format["json_schema"]
+            .get("schema")
+            .filter(|s| s.is_object())
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| "response_format.json_schema.schema is required".to_string()),
+        Some(other) => Err(format!("Unsupported response_format type: {}", other)),
+        None => Err("response_format.type is required".to_string()),
+    }
+}
+
+/// Map OpenAI reasoning_effort to a Gemini thinkingConfig
+///
+/// Gemini 3 models take a thinking level, older models a token budget.
+fn build_thinking_config(effort: &str, mapped_model: &str) -> Result<Value, String> {
+    if mapped_model.contains("gemini-3") {
+        let level = match effort {
+            "none" | "minimal" | "low" => "low",
+            "medium" | "high" => "high",
+            other => return Err(format!("Invalid reasoning_effort: {}", other)),
+        };
+        return Ok(json!({ "includeThoughts": true, "thinkingLevel": level }));
+    }
+
+    let budget = match effort {
+        // Pro models cannot disable thinking entirely, 128 is their minimum budget
+        "none" | "minimal" if mapped_model.contains("pro") => 128,
+        "none" | "minimal" => 0,
+        "low" => 1024,
+        "medium" => 8192,
+        "high" => 24576,
+        other => return Err(format!("Invalid reasoning_effort: {}", other)),
+    };
+    Ok(json!({ "includeThoughts": budget > 0, "thinkingBudget": budget }))
+}
+
+/// Legacy `function_call: "auto" | "none" | {"name": ...}` → tool_choice
+fn legacy_function_call_to_tool_choice(function_call: &Value) -> Value {
+    match function_call.get("name") {
+        Some(name) => json!({ "type": "function", "function": { "name": name } }),
+        None => function_call.clone(),
+    }
+}
+
+/// 构建 Contents (Messages)
+///
+/// - `assistant.tool_calls` → `functionCall` parts (model role)
+/// - `role: "tool"` / legacy `role: "function"` → `functionResponse` parts (user role)
+fn build_contents(messages: &[Value]) -> Result<Vec<Value>, String> {
+    let mut contents = Vec::new();
+    // tool_call_id -> function name, needed because tool messages only carry the id
+    let mut tool_id_to_name: HashMap<String, String> = HashMap::new();
+
+    for msg in messages {
+        let role = msg["role"].as_str().unwrap_or("user");
+        let mut parts = Vec::new();
+
+        match role {
+            "system" | "developer" => continue,
+            "assistant" => {
+                parts.extend(build_content_parts(&msg["content"])?);
+
+                for call in msg["tool_calls"].as_array().into_iter().flatten() {
+                    let id = call["id"].as_str().unwrap_or_default().to_string();
+                    let name = call["function"]["name"]
+                        .as_str()
+                        .ok_or_else(|| "tool_calls[].function.name is required".to_string())?
+                        .to_string();
+                    let args = parse_tool_arguments(&call["function"]["arguments"]);
+
+                    let mut part = json!({
+                        "functionCall": {
+                            "name": name,
+                            "args": args,
+                            "id": id
+                        }
+                    });
+
+                    // Clients never echo thought signatures back, recover them from the cache
+                    if let Some(sig) = SignatureCache::global().get_tool_signature(&id) {
+                        part["thoughtSignature"] = json!(sig);
+                    }
+
+                    tool_id_to_name.insert(id, name);
+                    parts.push(part);
+                }
+
+                // Legacy function_call (single call, no id)
+                if let Some(call) = msg.get("function_call").filter(|c| c.is_object()) {
+                    let name = call["name"].as_str().unwrap_or_default().to_string();
+                    parts.push(json!({
+                        "functionCall": {
+                            "name": name,
+                            "args": parse_tool_arguments(&call["arguments"])
+                        }
+                    }));
+                }
+
+                if parts.is_empty() {
+                    continue;
+                }
+                contents.push(json!({ "role": "model", "parts": parts }));
+            }
+            "tool" | "function" => {
+                let tool_call_id = msg["tool_call_id"].as_str().unwrap_or_default();
+                let name = tool_id_to_name
+                    .get(tool_call_id)
+                    .cloned()
+                    .or_else(|| msg["name"].as_str().map(|s| s.to_string()))
+                    .unwrap_or_else(|| tool_call_id.to_string());
+
+                let result = content_to_text(&msg["content"]);
+
+                let mut function_response = json!({
+                    "name": name,
+                    "response": { "result": result }
+                });
+                if !tool_call_id.is_empty() {
+                    function_response["id"] = json!(tool_call_id);
+                }
+
+                contents.push(json!({
+                    "role": "user",
+                    "parts": [{ "functionResponse": function_response }]
+                }));
+            }
+            _ => {
+                parts.extend(build_content_parts(&msg["content"])?);
+                if parts.is_empty() {
+                    parts.push(json!({ "text": "" }));
+                }
+                contents.push(json!({ "role": "user", "parts": parts }));
+            }
+        }
+    }
+
+    // Consecutive tool results must be sent as a single user turn
+    Ok(merge_adjacent_roles(contents))
+}
+
+/// Flatten message content (string or array of parts) to plain text
+fn content_to_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// 构建 message content parts (text / image_url / input_audio / file → Gemini parts)
+fn build_content_parts(content: &Value) -> Result<Vec<Value>, String> {
+    let blocks = match content {
+        Value::Null => return Ok(Vec::new()),
+        Value::String(s) if s.is_empty() => return Ok(Vec::new()),
+        Value::String(s) => return Ok(vec![json!({ "text": s })]),
+        Value::Array(blocks) => blocks,
+        other => return Ok(vec![json!({ "text": other.to_string() })]),
+    };
+
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block["type"].as_str().unwrap_or("text") {
+            "text" | "input_text" | "output_text" => {
+                if let Some(text) = block["text"].as_str().filter(|t| !t.is_empty()) {
+                    parts.push(json!({ "text": text }));
+                }
+            }
+            "image_url" => {
+                // Both `{"image_url": {"url": ...}}` and the shorthand `{"image_url": "..."}` are accepted
+                let url = block["image_url"]["url"]
+                    .as_str()
+                    .or_else(|| block["image_url"].as_str())
+                    .ok_or_else(|| "image_url.url is required".to_string())?;
+                parts.push(inline_data_from_url(url)?);
+            }
+            "input_audio" => {
+                let audio = &block["input_audio"];
+                let data = audio["data"]
+                    .as_str()
+                    .ok_or_else(|| "input_audio.data is required".to_string())?;
+                let format = audio["format"].as_str().unwrap_or("wav");
+                parts.push(json!({
+                    "inlineData": {
+                        "mimeType": audio_mime_type(format),
+                        "data": data
+                    }
+                }));
+            }
+            "file" => {
+                let file = &block["file"];
+                if file["file_id"].is_string() && !file["file_data"].is_string() {
+                    return Err("file_id references are not supported, send file_data instead".to_string());
+                }
+                let file_data = file["file_data"]
+                    .as_str()
+                    .ok_or_else(|| "file.file_data is required".to_string())?;
+                parts.push(inline_data_from_url(file_data)?);
+            }
+            other => return Err(format!("Unsupported content part type: {}", other)),
+        }
+    }
+
+    Ok(parts)
+}
+
+/// Parse a `data:<mime>;base64,<data>` URL into a Gemini inlineData part
+fn inline_data_from_url(url: &str) -> Result<Value, String> {
+    let rest = url.strip_prefix("data:").ok_or_else(|| {
+        "Only base64 data: URLs are supported for images and files".to_string()
+    })?;
+    let (meta, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "Malformed data: URL".to_string())?;
+    let mime_type = meta
+        .strip_suffix(";base64")
+        .ok_or_else(|| "data: URLs must be base64 encoded".to_string())?;
+
+    Ok(json!({
+        "inlineData": {
+            "mimeType": if mime_type.is_empty() { "application/octet-stream" } else { mime_type },
+            "data": data
+        }
+    }))
+}
+
+/// Map an OpenAI `input_audio.format` to a MIME type
+fn audio_mime_type(format: &str) -> String {
+    match format {
+        "mp3" => "audio/mpeg".to_string(),
+        "wav" => "audio/wav".to_string(),
+        other => format!("audio/{}", other),
+    }
+}
+
+/// OpenAI sends arguments as a JSON string; Gemini expects an object
+fn parse_tool_arguments(arguments: &Value) -> Value {
+    match arguments {
+        Value::String(s) if s.trim().is_empty() => json!({}),
+        Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| json!({ "input": s })),
+        Value::Null => json!({}),
+        other => other.clone(),
+    }
+}
+
+/// 构建 Tools (OpenAI tools → Gemini functionDeclarations)
+fn build_tools(tools: Option<&Value>) -> Result<Option<Value>, String> {
+    let tools = match tools.and_then(|t| t.as_array()) {
+        Some(t) if !t.is_empty() => t,
+        _ => return Ok(None),
+    };
+
+    let mut function_declarations = Vec::new();
+    for tool in tools {
+        let tool_type = tool["type"].as_str().unwrap_or("function");
+        if tool_type != "function" {
+            return Err(format!("Unsupported tool type: {}", tool_type));
+        }
+
+        let function = &tool["function"];
+        let name = function["name"]
+            .as_str()
+            .ok_or_else(|| "tools[].function.name is required".to_string())?;
+
+        let mut parameters = function
+            .get("parameters")
+            .filter(|p| !p.is_null())
+            .cloned()
+            .unwrap_or_else(|| json!({ "type": "object", "properties": {} }));
+        clean_json_schema(&mut parameters);
+
+        let mut declaration = json!({
+            "name": name,
+            "parameters": parameters
+        });
+        if let Some(description) = function["description"].as_str() {
+            declaration["description"] = json!(description);
+        }
+        function_declarations.push(declaration);
+    }
+
+    Ok(Some(json!([{ "functionDeclarations": function_declarations }])))
+}
+
+/// 构建 toolConfig (OpenAI tool_choice → Gemini functionCallingConfig)
+fn build_tool_config(tool_choice: Option<&Value>) -> Result<Value, String> {
+    let config = match tool_choice {
+        None | Some(Value::Null) => json!({ "mode": "VALIDATED" }),
+        Some(Value::String(choice)) => match choice.as_str() {
+            "auto" => json!({ "mode": "VALIDATED" }),
+            "none" => json!({ "mode": "NONE" }),
+            "required" => json!({ "mode": "ANY" }),
+            other => return Err(format!("Invalid tool_choice: {}", other)),
+        },
+        Some(Value::Object(obj)) => {
+            let name = obj
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(|n| n.as_str())
+                .ok_or_else(|| "tool_choice.function.name is required".to_string())?;
+            json!({ "mode": "ANY", "allowedFunctionNames": [name] })
+        }
+        Some(other) => return Err(format!("Invalid tool_choice: {}", other)),
+    };
+
+    Ok(json!({ "functionCallingConfig": config }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tools_and_tool_choice() {
+        let payload = json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "Weather in Paris?" }],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Get weather",
+                    "parameters": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": { "city": { "type": ["string", "null"] } },
+                        "required": ["city"]
+                    }
+                }
+            }],
+            "tool_choice": { "type": "function", "function": { "name": "get_weather" } }
+        });
+
+        let body = transform_openai_request(&payload, "gemini-2.5-pro", "proj").unwrap();
+        let decl = &body["request"]["tools"][0]["functionDeclarations"][0];
+        assert_eq!(decl["name"], "get_weather");
+        assert!(decl["parameters"].get("additionalProperties").is_none());
+        assert_eq!(decl["parameters"]["properties"]["city"]["type"], "string");
+
+        let fcc = &body["request"]["toolConfig"]["functionCallingConfig"];
+        assert_eq!(fcc["mode"], "ANY");
+        assert_eq!(fcc["allowedFunctionNames"][0], "get_weather");
+    }
+
+    #[test]
+    fn test_tool_call_history() {
+        let payload = json!({
+            "messages": [
+                { "role": "user", "content": "Check both" },
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [
+                        { "id": "call_1", "type": "function", "function": { "name": "read", "arguments": "{\"path\":\"a\"}" } },
+                        { "id": "call_2", "type": "function", "function": { "name": "read", "arguments": "{\"path\":\"b\"}" } }
+                    ]
+                },
+                { "role": "tool", "tool_call_id": "call_1", "content": "A" },
+                { "role": "tool", "tool_call_id": "call_2", "content": "B" }
+            ]
+        });
+
+        let body = transform_openai_request(&payload, "gemini-2.5-pro", "proj").unwrap();
+        let contents = body["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+
+        let model_parts = contents[1]["parts"].as_array().unwrap();
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(model_parts[0]["functionCall"]["args"]["path"], "a");
+        assert_eq!(model_parts[1]["functionCall"]["id"], "call_2");
+
+        // Both tool results merged into one user turn
+        let user_parts = contents[2]["parts"].as_array().unwrap();
+        assert_eq!(contents[2]["role"], "user");
+        assert_eq!(user_parts.len(), 2);
+        assert_eq!(user_parts[0]["functionResponse"]["name"], "read");
+        assert_eq!(user_parts[1]["functionResponse"]["response"]["result"], "B");
+    }
+
+    #[test]
+    fn test_multimodal_content_parts() {
+        let payload = json!({
+            "messages": [
+                { "role": "system", "content": [{ "type": "text", "text": "Be brief" }] },
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "text", "text": "What is this?" },
+                        { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+                        { "type": "input_audio", "input_audio": { "data": "UklGRg==", "format": "mp3" } },
+                        { "type": "file", "file": { "filename": "a.pdf", "file_data": "data:application/pdf;base64,JVBERi0=" } }
+                    ]
+                }
+            ]
+        });
+
+        let body = transform_openai_request(&payload, "gemini-2.5-pro", "proj").unwrap();
+        assert_eq!(body["request"]["systemInstruction"]["parts"][0]["text"], "Be brief");
+
+        let parts = body["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0]["text"], "What is this?");
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/png");
+        assert_eq!(parts[1]["inlineData"]["data"], "iVBORw0KGgo=");
+        assert_eq!(parts[2]["inlineData"]["mimeType"], "audio/mpeg");
+        assert_eq!(parts[3]["inlineData"]["mimeType"], "application/pdf");
+    }
+
+    #[test]
+    fn test_remote_image_url_rejected() {
+        let payload = json!({
+            "messages": [{
+                "role": "user",
+                "content": [{ "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } }]
+            }]
+        });
+        assert!(transform_openai_request(&payload, "gemini-2.5-pro", "proj").is_err());
+    }
+
+    #[test]
+    fn test_sampling_params() {
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 100,
+            "max_completion_tokens": 200,
+            "top_p": 0.9,
+            "stop": ["END"],
+            "n": 2,
+            "seed": 42,
+            "presence_penalty": 0.5,
+            "frequency_penalty": 0.25,
+            "logprobs": true,
+            "top_logprobs": 3,
+            "reasoning_effort": "low"
+        });
+
+        let body = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap();
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["maxOutputTokens"], 200);
+        assert!(config.get("temperature").is_none());
+        assert_eq!(config["topP"], 0.9);
+        assert_eq!(config["stopSequences"][0], "END");
+        assert_eq!(config["candidateCount"], 2);
+        assert_eq!(config["seed"], 42);
+        assert_eq!(config["presencePenalty"], 0.5);
+        assert_eq!(config["frequencyPenalty"], 0.25);
+        assert_eq!(config["responseLogprobs"], true);
+        assert_eq!(config["logprobs"], 3);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 1024);
+    }
+
+    #[test]
+    fn test_max_tokens_clamped_to_model_limit() {
+        let payload = json!({ "messages": [{ "role": "user", "content": "hi" }], "max_tokens": 1_000_000 });
+        let body = transform_openai_request(&payload, "gemini-2.0-flash", "proj").unwrap();
+        assert_eq!(body["request"]["generationConfig"]["maxOutputTokens"], 8192);
+
+        // No client value: the model limit, same as /v1/messages
+        let payload = json!({ "messages": [{ "role": "user", "content": "hi" }] });
+        let body = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap();
+        assert_eq!(body["request"]["generationConfig"]["maxOutputTokens"], 65535);
+
+        for key in ["max_tokens", "max_completion_tokens"] {
+            for value in [0, -5] {
+                let payload = json!({ "messages": [{ "role": "user", "content": "hi" }], key: value });
+                let err = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap_err();
+                assert!(err.contains(key));
+            }
+        }
+    }
+
+    #[test]
+    fn test_reasoning_effort_levels() {
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "reasoning_effort": "medium"
+        });
+        let body = transform_openai_request(&payload, "gemini-3-pro-preview", "proj").unwrap();
+        assert_eq!(body["request"]["generationConfig"]["thinkingConfig"]["thinkingLevel"], "high");
+
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "reasoning_effort": "extreme"
+        });
+        assert!(transform_openai_request(&payload, "gemini-2.5-flash", "proj").is_err());
+    }
+
+    #[test]
+    fn test_unsupported_params_rejected() {
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "logit_bias": { "50256": -100 }
+        });
+        let err = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap_err();
+        assert!(err.contains("logit_bias"));
+
+        // Explicit nulls are treated as absent
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "logit_bias": null,
+            "user": "someone"
+        });
+        assert!(transform_openai_request(&payload, "gemini-2.5-flash", "proj").is_ok());
+    }
+
+    #[test]
+    fn test_response_format_json_schema() {
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "answer",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": { "value": { "$ref": "#/$defs/num" } },
+                        "required": ["value"],
+                        "additionalProperties": false,
+                        "$defs": { "num": { "type": ["integer", "null"] } }
+                    }
+                }
+            }
+        });
+
+        let body = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap();
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(config["responseSchema"]["properties"]["value"]["type"], "integer");
+        assert!(config["responseSchema"].get("$defs").is_none());
+
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "response_format": { "type": "json_object" }
+        });
+        let body = transform_openai_request(&payload, "gemini-2.5-flash", "proj").unwrap();
+        let config = &body["request"]["generationConfig"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert!(config.get("responseSchema").is_none());
+    }
+
+    #[test]
+    fn test_resolve_session_id() {
+        let mut payload = json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": "first question" }
+            ]
+        });
+        let by_turn = resolve_session_id(&payload, None);
+        assert!(by_turn.starts_with("turn:"));
+
+        payload["messages"].as_array_mut().unwrap().push(json!({ "role": "assistant", "content": "ok" }));
+        assert_eq!(resolve_session_id(&payload, None), by_turn);
+
+        payload["user"] = json!("droid-user");
+        assert_eq!(resolve_session_id(&payload, None), "user:droid-user");
+        assert_eq!(resolve_session_id(&payload, Some("s-1")), "header:s-1");
+    }
+
+    #[test]
+    fn test_invalid_tool_choice() {
+        let payload = json!({
+            "messages": [{ "role": "user", "content": "hi" }],
+            "tools": [{ "type": "function", "function": { "name": "f" } }],
+            "tool_choice": "sometimes"
+        });
+        assert!(transform_openai_request(&payload, "gemini-2.5-pro", "proj").is_err());
+    }
+}
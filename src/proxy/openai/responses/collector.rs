@@ -0,0 +1,71 @@
+// Stream 收集器 - 将 Responses 事件流转换为完整的 response 对象
+// 用于非 Stream 请求的自动转换
+
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::Value;
+use std::io;
+
+/// 将 Responses SSE Stream 收集为最终的 response 对象
+///
+/// The terminal event (`response.completed` / `response.incomplete`) already
+/// carries the full response, so only that event is kept.
+pub async fn collect_responses_stream<S>(mut stream: S) -> Result<Value, String>
+where
+    S: futures::Stream<Item = Result<Bytes, io::Error>> + Unpin,
+{
+    let mut buffer = String::new();
+
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = chunk_result.map_err(|e| format!("Stream error: {}", e))?;
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(pos) = buffer.find('\n') {
+            let line: String = buffer.drain(..=pos).collect();
+            let data = match line.trim().strip_prefix("data:") {
+                Some(d) => d.trim(),
+                None => continue,
+            };
+
+            let event: Value = match serde_json::from_str(data) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            match event["type"].as_str() {
+                Some("response.completed") | Some("response.incomplete") => {
+                    return Ok(event["response"].clone());
+                }
+                Some("response.failed") | Some("error") => {
+                    return Err(format!("Stream error: {}", event));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Err("Stream ended without a response.completed event".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn test_collect_completed_response() {
+        let sse_data = vec![
+            "event: response.created\ndata: {\"type\":\"response.created\",\"response\":{\"status\":\"in_progress\"}}\n\n",
+            "event: response.completed\ndata: {\"type\":\"response.completed\",",
+            "\"response\":{\"id\":\"resp_1\",\"status\":\"completed\",\"output\":[]}}\n\n",
+        ];
+
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_responses_stream(byte_stream).await.unwrap();
+        assert_eq!(response["id"], "resp_1");
+        assert_eq!(response["status"], "completed");
+    }
+}
@@ -0,0 +1,115 @@
+// OpenAI Responses API (/v1/responses)
+// 负责 Responses ↔ Gemini 协议转换, 以及 previous_response_id 的本地存储
+
+pub mod collector;
+pub mod request;
+pub mod store;
+pub mod streaming;
+
+pub use collector::collect_responses_stream;
+pub use request::{transform_responses_request, ResponsesContext};
+pub use store::ResponseStore;
+pub use streaming::ResponsesStreamingState;
+
+use crate::proxy::error::ClientProtocol;
+use bytes::Bytes;
+use futures::Stream;
+use std::pin::Pin;
+
+/// 创建从 Gemini SSE 流到 Responses 事件流的转换
+pub fn create_responses_sse_stream(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+    email: String,
+    model: String,
+    context: ResponsesContext,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    use async_stream::stream;
+    use bytes::BytesMut;
+    use futures::StreamExt;
+
+    Box::pin(stream! {
+        let mut state = ResponsesStreamingState::new(context, &model);
+        let mut buffer = BytesMut::new();
+
+        while let Some(chunk_result) = gemini_stream.next().await {
+            match chunk_result {
+                Ok(chunk) => {
+                    buffer.extend_from_slice(&chunk);
+
+                    // Process complete lines
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line_raw = buffer.split_to(pos + 1);
+                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
+                            let line = line_str.trim();
+                            if line.is_empty() { continue; }
+
+                            for sse_chunk in process_sse_line(line, &mut state, &trace_id, &email) {
+                                yield Ok(sse_chunk);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Headers are already sent: report in-band and skip the normal termination
+                    yield Ok(ClientProtocol::Responses.sse_error_event(&format!("Upstream stream error: {}", e)));
+                    return;
+                }
+            }
+        }
+
+        // Ensure termination events are sent
+        for chunk in state.emit_finish() {
+            yield Ok(chunk);
+        }
+
+        // Only finished responses can be continued with previous_response_id
+        if let Some(history) = state.take_history() {
+            ResponseStore::global().insert(state.response_id(), history);
+        }
+    })
+}
+
+/// 处理单行 SSE 数据
+fn process_sse_line(
+    line: &str,
+    state: &mut ResponsesStreamingState,
+    trace_id: &str,
+    email: &str,
+) -> Vec<Bytes> {
+    let data_str = match line.strip_prefix("data:") {
+        Some(d) => d.trim(),
+        None => return vec![],
+    };
+
+    if data_str.is_empty() || data_str == "[DONE]" {
+        return vec![];
+    }
+
+    let json_value: serde_json::Value = match serde_json::from_str(data_str) {
+        Ok(v) => v,
+        Err(_) => return vec![],
+    };
+
+    // 解包 response 字段 (如果存在)
+    let raw_json = json_value.get("response").unwrap_or(&json_value);
+
+    if let Some(usage) = raw_json.get("usageMetadata") {
+        if raw_json
+            .get("candidates")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("finishReason"))
+            .is_some()
+        {
+            tracing::info!(
+                "[{}] ✓ Stream completed | Account: {} | In: {} tokens | Out: {} tokens",
+                trace_id,
+                email,
+                usage.get("promptTokenCount").and_then(|v| v.as_u64()).unwrap_or(0),
+                usage.get("candidatesTokenCount").and_then(|v| v.as_u64()).unwrap_or(0),
+            );
+        }
+    }
+
+    state.process_gemini_chunk(raw_json)
+}
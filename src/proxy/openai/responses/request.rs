@@ -0,0 +1,367 @@
+// Responses 请求转换 (/v1/responses → chat.completions 中间格式)
+//
+// Responses 的 input items 先转换为 chat.completions messages,
+// 然后复用 openai::request 完成到 Gemini 的转换。
+
+use super::store::ResponseStore;
+use serde_json::{json, Map, Value};
+
+/// Top-level Responses parameters we understand
+const RESPONSES_PARAMS: &[&str] = &[
+    "model",
+    "input",
+    "instructions",
+    "previous_response_id",
+    "tools",
+    "tool_choice",
+    "parallel_tool_calls",
+    "max_output_tokens",
+    "temperature",
+    "top_p",
+    "reasoning",
+    "text",
+    "stream",
+    "store",
+    "metadata",
+    "user",
+    "include",
+    "truncation",
+];
+
+/// Per-request data needed to build the Responses output
+#[derive(Debug, Clone)]
+pub struct ResponsesContext {
+    pub response_id: String,
+    /// Request fields echoed back on the response object
+    pub echo: Value,
+    /// Conversation to persist under `response_id` (None when `store: false`)
+    pub history: Option<Vec<Value>>,
+}
+
+/// 转换 Responses 请求为 chat.completions 请求
+pub fn transform_responses_request(payload: &Value) -> Result<(Value, ResponsesContext), String> {
+    let obj = payload
+        .as_object()
+        .ok_or_else(|| "Request body must be a JSON object".to_string())?;
+
+    for (key, value) in obj {
+        if !value.is_null() && !RESPONSES_PARAMS.contains(&key.as_str()) {
+            return Err(format!("Unsupported parameter: '{}' is not supported by this proxy", key));
+        }
+    }
+
+    if let Some(truncation) = payload["truncation"].as_str() {
+        if truncation != "disabled" {
+            return Err(format!("Unsupported truncation strategy: {}", truncation));
+        }
+    }
+
+    // History from previous_response_id (instructions are not carried over)
+    let mut history = match payload["previous_response_id"].as_str() {
+        Some(previous_id) => ResponseStore::global()
+            .get(previous_id)
+            .ok_or_else(|| format!("Previous response with id '{}' not found", previous_id))?,
+        None => Vec::new(),
+    };
+    history.extend(convert_input_items(&payload["input"])?);
+
+    let mut messages = Vec::new();
+    if let Some(instructions) = payload["instructions"].as_str().filter(|s| !s.is_empty()) {
+        messages.push(json!({ "role": "system", "content": instructions }));
+    }
+    messages.extend(history.iter().cloned());
+
+    let mut chat = Map::new();
+    chat.insert("model".to_string(), payload["model"].clone());
+    chat.insert("messages".to_string(), json!(messages));
+    chat.insert("stream".to_string(), json!(payload["stream"].as_bool().unwrap_or(false)));
+
+    for key in ["temperature", "top_p", "parallel_tool_calls", "metadata", "user"] {
+        if let Some(value) = payload.get(key).filter(|v| !v.is_null()) {
+            chat.insert(key.to_string(), value.clone());
+        }
+    }
+    if let Some(max_output_tokens) = payload.get("max_output_tokens").filter(|v| !v.is_null()) {
+        chat.insert("max_completion_tokens".to_string(), max_output_tokens.clone());
+    }
+    if let Some(effort) = payload["reasoning"].get("effort").filter(|v| !v.is_null()) {
+        chat.insert("reasoning_effort".to_string(), effort.clone());
+    }
+    if let Some(format) = payload["text"].get("format").filter(|v| !v.is_null()) {
+        chat.insert("response_format".to_string(), convert_text_format(format));
+    }
+    if let Some(tools) = payload["tools"].as_array().filter(|t| !t.is_empty()) {
+        chat.insert("tools".to_string(), json!(convert_tools(tools)?));
+    }
+    if let Some(tool_choice) = payload.get("tool_choice").filter(|v| !v.is_null()) {
+        chat.insert("tool_choice".to_string(), convert_tool_choice(tool_choice));
+    }
+
+    let store = payload["store"].as_bool().unwrap_or(true);
+    let context = ResponsesContext {
+        response_id: format!("resp_{}", uuid::Uuid::new_v4().simple()),
+        echo: json!({
+            "instructions": payload["instructions"],
+            "previous_response_id": payload["previous_response_id"],
+            "tools": payload.get("tools").cloned().unwrap_or_else(|| json!([])),
+            "tool_choice": payload.get("tool_choice").filter(|v| !v.is_null()).cloned().unwrap_or_else(|| json!("auto")),
+            "parallel_tool_calls": payload["parallel_tool_calls"].as_bool().unwrap_or(true),
+            "temperature": payload["temperature"],
+            "top_p": payload["top_p"],
+            "max_output_tokens": payload["max_output_tokens"],
+            "reasoning": payload["reasoning"],
+            "text": payload.get("text").cloned().unwrap_or_else(|| json!({ "format": { "type": "text" } })),
+            "store": store,
+            "metadata": payload.get("metadata").cloned().unwrap_or_else(|| json!({})),
+        }),
+        history: if store { Some(history) } else { None },
+    };
+
+    Ok((Value::Object(chat), context))
+}
+
+/// 转换 input (string 或 item 数组) 为 chat messages
+fn convert_input_items(input: &Value) -> Result<Vec<Value>, String> {
+    let items = match input {
+        Value::Null => return Ok(Vec::new()),
+        Value::String(text) => return Ok(vec![json!({ "role": "user", "content": text })]),
+        Value::Array(items) => items,
+        _ => return Err("input must be a string or an array of items".to_string()),
+    };
+
+    let mut messages: Vec<Value> = Vec::new();
+    for item in items {
+        // Items without a type are shorthand messages ({"role": ..., "content": ...})
+        match item["type"].as_str().unwrap_or("message") {
+            "message" => {
+                let role = item["role"].as_str().unwrap_or("user");
+                let content = match &item["content"] {
+                    Value::Array(blocks) => json!(blocks
+                        .iter()
+                        .map(convert_content_block)
+                        .collect::<Result<Vec<_>, _>>()?),
+                    other => other.clone(),
+                };
+                messages.push(json!({ "role": role, "content": content }));
+            }
+            "function_call" => {
+                let call_id = item["call_id"]
+                    .as_str()
+                    .ok_or_else(|| "function_call.call_id is required".to_string())?;
+                let tool_call = json!({
+                    "id": call_id,
+                    "type": "function",
+                    "function": {
+                        "name": item["name"],
+                        "arguments": item["arguments"].as_str().unwrap_or("{}")
+                    }
+                });
+
+                // Parallel calls arrive as consecutive items; keep them on one assistant turn
+                match messages.last_mut() {
+                    Some(last) if last["role"] == "assistant" && last["tool_calls"].is_array() => {
+                        last["tool_calls"].as_array_mut().unwrap().push(tool_call);
+                    }
+                    _ => messages.push(json!({
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [tool_call]
+                    })),
+                }
+            }
+            "function_call_output" => {
+                let output = match &item["output"] {
+                    Value::String(s) => json!(s),
+                    Value::Array(blocks) => json!(blocks
+                        .iter()
+                        .map(convert_content_block)
+                        .collect::<Result<Vec<_>, _>>()?),
+                    other => json!(other.to_string()),
+                };
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": item["call_id"],
+                    "content": output
+                }));
+            }
+            "reasoning" => {
+                // Gemini thoughts cannot be replayed; tool call signatures are restored by call id
+                tracing::debug!("[Responses] Skipping reasoning input item");
+            }
+            "item_reference" => {
+                return Err("item_reference input items are not supported".to_string());
+            }
+            other => return Err(format!("Unsupported input item type: {}", other)),
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Responses content block → chat.completions content part
+fn convert_content_block(block: &Value) -> Result<Value, String> {
+    match block["type"].as_str().unwrap_or_default() {
+        "input_text" | "output_text" => Ok(json!({ "type": "text", "text": block["text"] })),
+        "refusal" => Ok(json!({ "type": "text", "text": block["refusal"] })),
+        "input_image" => {
+            let url = block["image_url"]
+                .as_str()
+                .or_else(|| block["image_url"]["url"].as_str())
+                .ok_or_else(|| "input_image.image_url is required".to_string())?;
+            Ok(json!({ "type": "image_url", "image_url": { "url": url } }))
+        }
+        "input_file" => Ok(json!({
+            "type": "file",
+            "file": {
+                "filename": block["filename"],
+                "file_data": block["file_data"],
+                "file_id": block["file_id"]
+            }
+        })),
+        "input_audio" => Ok(json!({ "type": "input_audio", "input_audio": block["input_audio"] })),
+        other => Err(format!("Unsupported content type: {}", other)),
+    }
+}
+
+/// Responses function tools are flat, chat.completions nests them under `function`
+fn convert_tools(tools: &[Value]) -> Result<Vec<Value>, String> {
+    tools
+        .iter()
+        .map(|tool| match tool["type"].as_str().unwrap_or("function") {
+            "function" => Ok(json!({
+                "type": "function",
+                "function": {
+                    "name": tool["name"],
+                    "description": tool["description"],
+                    "parameters": tool["parameters"]
+                }
+            })),
+            other => Err(format!("Unsupported tool type: {}", other)),
+        })
+        .collect()
+}
+
+/// `{"type": "function", "name": ...}` → `{"type": "function", "function": {"name": ...}}`
+fn convert_tool_choice(tool_choice: &Value) -> Value {
+    match tool_choice.get("name") {
+        Some(name) => json!({ "type": "function", "function": { "name": name } }),
+        None => tool_choice.clone(),
+    }
+}
+
+/// Responses flattens json_schema fields into `text.format`
+fn convert_text_format(format: &Value) -> Value {
+    if format["type"] == "json_schema" {
+        json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": format["name"],
+                "schema": format["schema"],
+                "strict": format["strict"]
+            }
+        })
+    } else {
+        format.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_items_to_messages() {
+        let payload = json!({
+            "model": "gemini-2.5-pro",
+            "instructions": "Be brief",
+            "input": [
+                { "role": "user", "content": [{ "type": "input_text", "text": "Read both files" }] },
+                { "type": "reasoning", "id": "rs_1", "summary": [] },
+                { "type": "function_call", "call_id": "call_a", "name": "read", "arguments": "{\"path\":\"a\"}" },
+                { "type": "function_call", "call_id": "call_b", "name": "read", "arguments": "{\"path\":\"b\"}" },
+                { "type": "function_call_output", "call_id": "call_a", "output": "A" },
+                { "type": "function_call_output", "call_id": "call_b", "output": "B" }
+            ],
+            "tools": [{ "type": "function", "name": "read", "parameters": { "type": "object" } }],
+            "tool_choice": { "type": "function", "name": "read" },
+            "max_output_tokens": 256
+        });
+
+        let (chat, context) = transform_responses_request(&payload).unwrap();
+        let messages = chat["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"][0]["type"], "text");
+        assert_eq!(messages[2]["tool_calls"].as_array().unwrap().len(), 2);
+        assert_eq!(messages[4]["tool_call_id"], "call_b");
+        assert_eq!(chat["tools"][0]["function"]["name"], "read");
+        assert_eq!(chat["tool_choice"]["function"]["name"], "read");
+        assert_eq!(chat["max_completion_tokens"], 256);
+
+        // Instructions are not part of the stored history
+        assert_eq!(context.history.unwrap().len(), 4);
+        assert!(context.response_id.starts_with("resp_"));
+    }
+
+    #[test]
+    fn test_previous_response_id_chaining() {
+        ResponseStore::global().insert(
+            "resp_test_chain",
+            vec![
+                json!({ "role": "user", "content": "My name is Ann" }),
+                json!({ "role": "assistant", "content": "Hi Ann" }),
+            ],
+        );
+
+        let payload = json!({
+            "model": "gemini-2.5-flash",
+            "input": "What is my name?",
+            "previous_response_id": "resp_test_chain",
+            "store": false
+        });
+        let (chat, context) = transform_responses_request(&payload).unwrap();
+        assert_eq!(chat["messages"].as_array().unwrap().len(), 3);
+        assert!(context.history.is_none());
+
+        let payload = json!({ "model": "gemini-2.5-flash", "input": "hi", "previous_response_id": "resp_unknown" });
+        assert!(transform_responses_request(&payload).is_err());
+    }
+
+    #[test]
+    fn test_droid_chat_completions_payload() {
+        // Factory Droid posts Responses-shaped bodies to /v1/chat/completions
+        let payload = json!({
+            "model": "gpt-4o",
+            "instructions": "You are Droid",
+            "input": [
+                { "role": "user", "content": [{ "type": "input_text", "text": "List the repo" }] }
+            ],
+            "tools": [{
+                "type": "function",
+                "name": "list_dir",
+                "description": "List a directory",
+                "parameters": { "type": "object", "properties": { "path": { "type": "string" } } },
+                "strict": false
+            }],
+            "include": ["reasoning.encrypted_content"],
+            "truncation": "disabled",
+            "parallel_tool_calls": true,
+            "store": false,
+            "stream": true
+        });
+
+        let (chat, _) = transform_responses_request(&payload).unwrap();
+        let body = crate::proxy::openai::transform_openai_request(&chat, "gemini-2.5-pro", "proj").unwrap();
+        assert_eq!(body["request"]["tools"][0]["functionDeclarations"][0]["name"], "list_dir");
+        assert_eq!(body["request"]["systemInstruction"]["parts"][0]["text"], "You are Droid");
+    }
+
+    #[test]
+    fn test_unsupported_params_rejected() {
+        let payload = json!({ "model": "gemini-2.5-flash", "input": "hi", "background": true });
+        assert!(transform_responses_request(&payload).is_err());
+
+        let payload = json!({ "model": "gemini-2.5-flash", "input": "hi", "tools": [{ "type": "web_search_preview" }] });
+        assert!(transform_responses_request(&payload).is_err());
+    }
+}
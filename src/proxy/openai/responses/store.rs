@@ -0,0 +1,97 @@
+// Responses 本地存储 - 支持 previous_response_id 会话链
+//
+// 上游 Gemini 是无状态的, 所以每个已完成的 response 都以 chat.completions
+// 消息的形式保存在本地, 后续请求通过 previous_response_id 取回完整历史。
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+const RESPONSE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+const MAX_STORED_RESPONSES: usize = 1000;
+
+#[derive(Clone, Debug)]
+struct StoredResponse {
+    /// Full conversation (without instructions) up to and including this response
+    messages: Vec<Value>,
+    timestamp: SystemTime,
+}
+
+impl StoredResponse {
+    fn is_expired(&self) -> bool {
+        self.timestamp.elapsed().unwrap_or(Duration::ZERO) > RESPONSE_TTL
+    }
+}
+
+/// In-memory store of completed responses keyed by response id
+pub struct ResponseStore {
+    responses: Mutex<HashMap<String, StoredResponse>>,
+}
+
+impl ResponseStore {
+    fn new() -> Self {
+        Self {
+            responses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Global singleton instance
+    pub fn global() -> &'static ResponseStore {
+        static INSTANCE: OnceLock<ResponseStore> = OnceLock::new();
+        INSTANCE.get_or_init(ResponseStore::new)
+    }
+
+    /// Save the conversation that produced `response_id`
+    pub fn insert(&self, response_id: &str, messages: Vec<Value>) {
+        if let Ok(mut responses) = self.responses.lock() {
+            if responses.len() >= MAX_STORED_RESPONSES {
+                responses.retain(|_, v| !v.is_expired());
+            }
+            // Still full: drop the oldest entry
+            if responses.len() >= MAX_STORED_RESPONSES {
+                if let Some(oldest) = responses
+                    .iter()
+                    .min_by_key(|(_, v)| v.timestamp)
+                    .map(|(k, _)| k.clone())
+                {
+                    responses.remove(&oldest);
+                }
+            }
+            tracing::debug!("[ResponseStore] Stored {} ({} messages)", response_id, messages.len());
+            responses.insert(
+                response_id.to_string(),
+                StoredResponse {
+                    messages,
+                    timestamp: SystemTime::now(),
+                },
+            );
+        }
+    }
+
+    /// Conversation history for a previous response
+    pub fn get(&self, response_id: &str) -> Option<Vec<Value>> {
+        let responses = self.responses.lock().ok()?;
+        responses
+            .get(response_id)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.messages.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_insert_and_get() {
+        let store = ResponseStore::new();
+        assert!(store.get("resp_missing").is_none());
+
+        store.insert("resp_1", vec![json!({ "role": "user", "content": "hi" })]);
+        let messages = store.get("resp_1").unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["content"], "hi");
+    }
+}
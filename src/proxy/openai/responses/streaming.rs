@@ -0,0 +1,486 @@
+// Responses 流式响应转换 (Gemini SSE → Responses streaming events)
+
+use super::request::ResponsesContext;
+use crate::proxy::claude::models::{GeminiPart, UsageMetadata};
+use crate::proxy::SignatureCache;
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+/// Convert Gemini UsageMetadata to a Responses usage object
+pub fn to_responses_usage(usage: &UsageMetadata) -> Value {
+    let input_tokens = usage.prompt_token_count.unwrap_or(0);
+    let reasoning_tokens = usage.thoughts_token_count.unwrap_or(0);
+    let output_tokens = usage.candidates_token_count.unwrap_or(0) + reasoning_tokens;
+    json!({
+        "input_tokens": input_tokens,
+        "input_tokens_details": { "cached_tokens": usage.cached_content_token_count.unwrap_or(0) },
+        "output_tokens": output_tokens,
+        "output_tokens_details": { "reasoning_tokens": reasoning_tokens },
+        "total_tokens": usage.total_token_count.unwrap_or(input_tokens + output_tokens),
+    })
+}
+
+/// 当前正在输出的 item
+enum OpenItem {
+    Message { item_id: String, text: String },
+    Reasoning { item_id: String, text: String },
+}
+
+/// Responses 流式状态机
+pub struct ResponsesStreamingState {
+    context: ResponsesContext,
+    model: String,
+    created_at: i64,
+    sequence_number: u64,
+    /// Completed output items
+    output: Vec<Value>,
+    current: Option<OpenItem>,
+    usage: Option<UsageMetadata>,
+    finish_reason: Option<String>,
+    started: bool,
+    /// `parallel_tool_calls: false`: 只保留第一个 functionCall
+    parallel_tool_calls: bool,
+    used_tool: bool,
+    pub done_sent: bool,
+}
+
+impl ResponsesStreamingState {
+    pub fn new(context: ResponsesContext, model: &str) -> Self {
+        Self {
+            parallel_tool_calls: context.echo["parallel_tool_calls"].as_bool().unwrap_or(true),
+            used_tool: false,
+            context,
+            model: model.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+            sequence_number: 0,
+            output: Vec::new(),
+            current: None,
+            usage: None,
+            finish_reason: None,
+            started: false,
+            done_sent: false,
+        }
+    }
+
+    pub fn response_id(&self) -> &str {
+        &self.context.response_id
+    }
+
+    /// 发送 SSE 事件 (`event:` + `data:`)
+    fn emit(&mut self, event_type: &str, mut data: Value) -> Bytes {
+        data["type"] = json!(event_type);
+        data["sequence_number"] = json!(self.sequence_number);
+        self.sequence_number += 1;
+        Bytes::from(format!(
+            "event: {}\ndata: {}\n\n",
+            event_type,
+            serde_json::to_string(&data).unwrap_or_default()
+        ))
+    }
+
+    /// Build the response object in its current state
+    pub fn response_object(&self, status: &str) -> Value {
+        let mut response = json!({
+            "id": self.context.response_id,
+            "object": "response",
+            "created_at": self.created_at,
+            "status": status,
+            "model": self.model,
+            "output": self.output,
+            "error": null,
+            "incomplete_details": null,
+            "usage": self.usage.as_ref().map(to_responses_usage),
+        });
+        if let (Some(target), Some(echo)) = (response.as_object_mut(), self.context.echo.as_object()) {
+            for (key, value) in echo {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+        response
+    }
+
+    fn ensure_started(&mut self) -> Vec<Bytes> {
+        if self.started {
+            return Vec::new();
+        }
+        self.started = true;
+        let response = self.response_object("in_progress");
+        vec![
+            self.emit("response.created", json!({ "response": response })),
+            self.emit("response.in_progress", json!({ "response": response })),
+        ]
+    }
+
+    /// 处理一个 Gemini 响应片段 (已解包 response 字段)
+    pub fn process_gemini_chunk(&mut self, raw_json: &Value) -> Vec<Bytes> {
+        let mut chunks = self.ensure_started();
+
+        if let Some(usage) = raw_json
+            .get("usageMetadata")
+            .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+        {
+            self.usage = Some(usage);
+        }
+
+        // Responses has a single output list, only the first candidate is used
+        let candidate = match raw_json.get("candidates").and_then(|c| c.get(0)) {
+            Some(c) => c,
+            None => return chunks,
+        };
+
+        if let Some(parts) = candidate
+            .get("content")
+            .and_then(|c| c.get("parts"))
+            .and_then(|p| p.as_array())
+        {
+            for part_value in parts {
+                if let Ok(part) = serde_json::from_value::<GeminiPart>(part_value.clone()) {
+                    chunks.extend(self.process_part(&part));
+                }
+            }
+        }
+
+        if let Some(finish_reason) = candidate.get("finishReason").and_then(|f| f.as_str()) {
+            self.finish_reason = Some(finish_reason.to_string());
+        }
+
+        chunks
+    }
+
+    /// 处理单个 part
+    fn process_part(&mut self, part: &GeminiPart) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+
+        if let Some(text) = part.text.as_ref().filter(|t| !t.is_empty()) {
+            if part.thought.unwrap_or(false) {
+                chunks.extend(self.append_reasoning(text));
+            } else {
+                chunks.extend(self.append_text(text));
+            }
+        }
+
+        if let Some(img) = part.inline_data.as_ref().filter(|img| !img.data.is_empty()) {
+            let markdown_img = format!("![image](data:{};base64,{})", img.mime_type, img.data);
+            chunks.extend(self.append_text(&markdown_img));
+        }
+
+        if let Some(fc) = &part.function_call {
+            // parallel_tool_calls: false: 丢弃第一个之后的 functionCall
+            if !self.parallel_tool_calls && self.used_tool {
+                tracing::debug!("[Responses-Stream] Dropping parallel function call '{}'", fc.name);
+                return chunks;
+            }
+            self.used_tool = true;
+            chunks.extend(self.close_current());
+
+            let call_id = fc
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}", crate::proxy::common::utils::generate_random_id()));
+            if let Some(sig) = &part.thought_signature {
+                SignatureCache::global().cache_tool_signature(&call_id, sig.clone());
+            }
+
+            let item_id = format!("fc_{}", uuid::Uuid::new_v4().simple());
+            let output_index = self.output.len();
+            let arguments = fc
+                .args
+                .as_ref()
+                .map(|a| serde_json::to_string(a).unwrap_or_default())
+                .unwrap_or_else(|| "{}".to_string());
+
+            let mut item = json!({
+                "id": item_id,
+                "type": "function_call",
+                "status": "in_progress",
+                "call_id": call_id,
+                "name": fc.name,
+                "arguments": ""
+            });
+            chunks.push(self.emit("response.output_item.added", json!({ "output_index": output_index, "item": item })));
+            chunks.push(self.emit(
+                "response.function_call_arguments.delta",
+                json!({ "item_id": item_id, "output_index": output_index, "delta": arguments }),
+            ));
+            chunks.push(self.emit(
+                "response.function_call_arguments.done",
+                json!({ "item_id": item_id, "output_index": output_index, "arguments": arguments }),
+            ));
+
+            item["status"] = json!("completed");
+            item["arguments"] = json!(arguments);
+            chunks.push(self.emit("response.output_item.done", json!({ "output_index": output_index, "item": item })));
+            self.output.push(item);
+        }
+
+        chunks
+    }
+
+    fn append_text(&mut self, text: &str) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        if !matches!(self.current, Some(OpenItem::Message { .. })) {
+            chunks.extend(self.close_current());
+
+            let item_id = format!("msg_{}", uuid::Uuid::new_v4().simple());
+            let output_index = self.output.len();
+            chunks.push(self.emit(
+                "response.output_item.added",
+                json!({
+                    "output_index": output_index,
+                    "item": { "id": item_id, "type": "message", "status": "in_progress", "role": "assistant", "content": [] }
+                }),
+            ));
+            chunks.push(self.emit(
+                "response.content_part.added",
+                json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "part": { "type": "output_text", "text": "", "annotations": [] }
+                }),
+            ));
+            self.current = Some(OpenItem::Message { item_id, text: String::new() });
+        }
+
+        let output_index = self.output.len();
+        if let Some(OpenItem::Message { item_id, text: acc }) = &mut self.current {
+            acc.push_str(text);
+            let item_id = item_id.clone();
+            chunks.push(self.emit(
+                "response.output_text.delta",
+                json!({ "item_id": item_id, "output_index": output_index, "content_index": 0, "delta": text }),
+            ));
+        }
+        chunks
+    }
+
+    fn append_reasoning(&mut self, text: &str) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        if !matches!(self.current, Some(OpenItem::Reasoning { .. })) {
+            chunks.extend(self.close_current());
+
+            let item_id = format!("rs_{}", uuid::Uuid::new_v4().simple());
+            let output_index = self.output.len();
+            chunks.push(self.emit(
+                "response.output_item.added",
+                json!({
+                    "output_index": output_index,
+                    "item": { "id": item_id, "type": "reasoning", "summary": [] }
+                }),
+            ));
+            chunks.push(self.emit(
+                "response.reasoning_summary_part.added",
+                json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "part": { "type": "summary_text", "text": "" }
+                }),
+            ));
+            self.current = Some(OpenItem::Reasoning { item_id, text: String::new() });
+        }
+
+        let output_index = self.output.len();
+        if let Some(OpenItem::Reasoning { item_id, text: acc }) = &mut self.current {
+            acc.push_str(text);
+            let item_id = item_id.clone();
+            chunks.push(self.emit(
+                "response.reasoning_summary_text.delta",
+                json!({ "item_id": item_id, "output_index": output_index, "summary_index": 0, "delta": text }),
+            ));
+        }
+        chunks
+    }
+
+    /// 结束当前打开的 message / reasoning item
+    fn close_current(&mut self) -> Vec<Bytes> {
+        let output_index = self.output.len();
+        match self.current.take() {
+            None => Vec::new(),
+            Some(OpenItem::Message { item_id, text }) => {
+                let part = json!({ "type": "output_text", "text": text, "annotations": [] });
+                let item = json!({
+                    "id": item_id,
+                    "type": "message",
+                    "status": "completed",
+                    "role": "assistant",
+                    "content": [part]
+                });
+                let chunks = vec![
+                    self.emit(
+                        "response.output_text.done",
+                        json!({ "item_id": item_id, "output_index": output_index, "content_index": 0, "text": text }),
+                    ),
+                    self.emit(
+                        "response.content_part.done",
+                        json!({ "item_id": item_id, "output_index": output_index, "content_index": 0, "part": part }),
+                    ),
+                    self.emit("response.output_item.done", json!({ "output_index": output_index, "item": item })),
+                ];
+                self.output.push(item);
+                chunks
+            }
+            Some(OpenItem::Reasoning { item_id, text }) => {
+                let part = json!({ "type": "summary_text", "text": text });
+                let item = json!({ "id": item_id, "type": "reasoning", "summary": [part] });
+                let chunks = vec![
+                    self.emit(
+                        "response.reasoning_summary_text.done",
+                        json!({ "item_id": item_id, "output_index": output_index, "summary_index": 0, "text": text }),
+                    ),
+                    self.emit(
+                        "response.reasoning_summary_part.done",
+                        json!({ "item_id": item_id, "output_index": output_index, "summary_index": 0, "part": part }),
+                    ),
+                    self.emit("response.output_item.done", json!({ "output_index": output_index, "item": item })),
+                ];
+                self.output.push(item);
+                chunks
+            }
+        }
+    }
+
+    /// 发送结束事件: response.completed 或 response.incomplete
+    pub fn emit_finish(&mut self) -> Vec<Bytes> {
+        if self.done_sent {
+            return Vec::new();
+        }
+        let mut chunks = self.ensure_started();
+        chunks.extend(self.close_current());
+
+        let incomplete_reason = match self.finish_reason.as_deref() {
+            Some("MAX_TOKENS") => Some("max_output_tokens"),
+            Some(reason) if super::super::streaming::map_finish_reason(reason) == "content_filter" => {
+                Some("content_filter")
+            }
+            _ => None,
+        };
+
+        let (event_type, mut response) = match incomplete_reason {
+            Some(reason) => {
+                let mut response = self.response_object("incomplete");
+                response["incomplete_details"] = json!({ "reason": reason });
+                ("response.incomplete", response)
+            }
+            None => ("response.completed", self.response_object("completed")),
+        };
+        if response["usage"].is_null() {
+            response["usage"] = json!({
+                "input_tokens": 0,
+                "input_tokens_details": { "cached_tokens": 0 },
+                "output_tokens": 0,
+                "output_tokens_details": { "reasoning_tokens": 0 },
+                "total_tokens": 0
+            });
+        }
+
+        chunks.push(self.emit(event_type, json!({ "response": response })));
+        self.done_sent = true;
+        chunks
+    }
+
+    /// Output items as chat.completions messages, for the response store
+    pub fn output_messages(&self) -> Vec<Value> {
+        let text: String = self
+            .output
+            .iter()
+            .filter(|item| item["type"] == "message")
+            .filter_map(|item| item["content"][0]["text"].as_str())
+            .collect();
+        let tool_calls: Vec<Value> = self
+            .output
+            .iter()
+            .filter(|item| item["type"] == "function_call")
+            .map(|item| {
+                json!({
+                    "id": item["call_id"],
+                    "type": "function",
+                    "function": { "name": item["name"], "arguments": item["arguments"] }
+                })
+            })
+            .collect();
+
+        if text.is_empty() && tool_calls.is_empty() {
+            return Vec::new();
+        }
+        let mut message = json!({ "role": "assistant", "content": if text.is_empty() { Value::Null } else { json!(text) } });
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = json!(tool_calls);
+        }
+        vec![message]
+    }
+
+    /// Conversation to persist once the response is complete
+    pub fn take_history(&mut self) -> Option<Vec<Value>> {
+        let mut history = self.context.history.take()?;
+        history.extend(self.output_messages());
+        Some(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ResponsesContext {
+        ResponsesContext {
+            response_id: "resp_test".to_string(),
+            echo: json!({ "store": true }),
+            history: Some(vec![json!({ "role": "user", "content": "hi" })]),
+        }
+    }
+
+    fn to_text(chunks: &[Bytes]) -> String {
+        chunks
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_text_and_function_call_events() {
+        let mut state = ResponsesStreamingState::new(context(), "gemini-2.5-pro");
+        let out = to_text(&state.process_gemini_chunk(&json!({
+            "candidates": [{ "content": { "parts": [
+                { "text": "Thinking...", "thought": true },
+                { "text": "Let me check" }
+            ] } }]
+        })));
+        assert!(out.starts_with("event: response.created\n"));
+        assert!(out.contains("event: response.reasoning_summary_text.delta"));
+        assert!(out.contains("event: response.output_text.delta"));
+        assert!(out.contains(r#""delta":"Let me check""#));
+
+        let out = to_text(&state.process_gemini_chunk(&json!({
+            "candidates": [{
+                "content": { "parts": [{ "functionCall": { "name": "read", "id": "call_r", "args": { "path": "a" } } }] },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "promptTokenCount": 5, "candidatesTokenCount": 3, "totalTokenCount": 8 }
+        })));
+        assert!(out.contains("event: response.output_text.done"));
+        assert!(out.contains("event: response.function_call_arguments.delta"));
+        assert!(out.contains(r#""call_id":"call_r""#));
+
+        let out = to_text(&state.emit_finish());
+        assert!(out.contains("event: response.completed"));
+        assert!(out.contains(r#""input_tokens":5"#));
+        assert!(state.emit_finish().is_empty());
+
+        let history = state.take_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1]["content"], "Let me check");
+        assert_eq!(history[1]["tool_calls"][0]["id"], "call_r");
+    }
+
+    #[test]
+    fn test_max_tokens_is_incomplete() {
+        let mut state = ResponsesStreamingState::new(context(), "gemini-2.5-flash");
+        state.process_gemini_chunk(&json!({
+            "candidates": [{ "content": { "parts": [{ "text": "cut" }] }, "finishReason": "MAX_TOKENS" }]
+        }));
+        let out = to_text(&state.emit_finish());
+        assert!(out.contains("event: response.incomplete"));
+        assert!(out.contains(r#""reason":"max_output_tokens""#));
+    }
+}
@@ -0,0 +1,448 @@
+// OpenAI 流式响应转换 (Gemini SSE → chat.completion.chunk)
+// 对应 StreamingState (Claude) 的 OpenAI 版本
+
+use crate::proxy::claude::models::{GeminiPart, UsageMetadata};
+use crate::proxy::SignatureCache;
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Map Gemini finishReason to OpenAI finish_reason
+pub fn map_finish_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ => "stop",
+    }
+}
+
+/// Convert Gemini UsageMetadata to OpenAI usage object
+pub fn to_openai_usage(usage: &UsageMetadata) -> Value {
+    let prompt_tokens = usage.prompt_token_count.unwrap_or(0);
+    // Gemini reports thinking tokens separately, OpenAI counts them as completion tokens
+    let reasoning_tokens = usage.thoughts_token_count.unwrap_or(0);
+    let completion_tokens = usage.candidates_token_count.unwrap_or(0) + reasoning_tokens;
+    let mut result = json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": usage.total_token_count.unwrap_or(prompt_tokens + completion_tokens),
+    });
+    if let Some(cached) = usage.cached_content_token_count {
+        result["prompt_tokens_details"] = json!({ "cached_tokens": cached });
+    }
+    if reasoning_tokens > 0 {
+        result["completion_tokens_details"] = json!({ "reasoning_tokens": reasoning_tokens });
+    }
+    result
+}
+
+/// Convert Gemini logprobsResult to an OpenAI `logprobs` object
+pub fn to_openai_logprobs(logprobs_result: &Value) -> Value {
+    let empty = Vec::new();
+    let chosen = logprobs_result["chosenCandidates"].as_array().unwrap_or(&empty);
+    let top = logprobs_result["topCandidates"].as_array().unwrap_or(&empty);
+
+    let content: Vec<Value> = chosen
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let top_logprobs: Vec<Value> = top
+                .get(i)
+                .and_then(|t| t["candidates"].as_array())
+                .map(|alternatives| {
+                    alternatives
+                        .iter()
+                        .map(|alt| json!({
+                            "token": alt["token"],
+                            "logprob": alt["logProbability"],
+                            "bytes": null
+                        }))
+                        .collect()
+                })
+                .unwrap_or_default();
+            json!({
+                "token": candidate["token"],
+                "logprob": candidate["logProbability"],
+                "bytes": null,
+                "top_logprobs": top_logprobs
+            })
+        })
+        .collect();
+
+    json!({ "content": content })
+}
+
+/// OpenAI 流式状态机
+pub struct OpenAIStreamingState {
+    id: String,
+    created: i64,
+    model: String,
+    include_usage: bool,
+    /// Choice indices that already received the `role` delta
+    role_sent: HashSet<u32>,
+    /// Choice indices that already received a finish_reason
+    finished: HashSet<u32>,
+    /// Number of tool calls emitted per choice (also the next `tool_calls[].index`)
+    tool_calls: HashMap<u32, u32>,
+    usage: Option<UsageMetadata>,
+    /// `parallel_tool_calls: false`: 只保留每个 choice 的第一个 functionCall
+    parallel_tool_calls: bool,
+    pub done_sent: bool,
+}
+
+impl OpenAIStreamingState {
+    pub fn new(model: &str, include_usage: bool) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_string(),
+            include_usage,
+            role_sent: HashSet::new(),
+            finished: HashSet::new(),
+            tool_calls: HashMap::new(),
+            usage: None,
+            parallel_tool_calls: true,
+            done_sent: false,
+        }
+    }
+
+    /// Gemini 无法关闭并行调用, 由本地丢弃多余的 functionCall
+    pub fn with_parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.parallel_tool_calls = parallel_tool_calls;
+        self
+    }
+
+    /// 发送 SSE data 行
+    fn emit(&self, data: Value) -> Bytes {
+        Bytes::from(format!(
+            "data: {}\n\n",
+            serde_json::to_string(&data).unwrap_or_default()
+        ))
+    }
+
+    /// 构建 chat.completion.chunk
+    fn emit_chunk(&self, index: u32, delta: Value, finish_reason: Option<&str>) -> Bytes {
+        let mut chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": index,
+                "delta": delta,
+                "finish_reason": finish_reason
+            }]
+        });
+        if self.include_usage {
+            chunk["usage"] = Value::Null;
+        }
+        self.emit(chunk)
+    }
+
+    /// Emit a delta, prefixing the assistant role on the first chunk of each choice
+    fn emit_delta(&mut self, index: u32, mut delta: Value) -> Bytes {
+        if self.role_sent.insert(index) {
+            delta["role"] = json!("assistant");
+        }
+        self.emit_chunk(index, delta, None)
+    }
+
+    /// 处理一个 Gemini 响应片段 (已解包 response 字段)
+    pub fn process_gemini_chunk(&mut self, raw_json: &Value) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+
+        if let Some(usage) = raw_json
+            .get("usageMetadata")
+            .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+        {
+            self.usage = Some(usage);
+        }
+
+        let candidates = match raw_json.get("candidates").and_then(|c| c.as_array()) {
+            Some(c) => c,
+            None => return chunks,
+        };
+
+        for (pos, candidate) in candidates.iter().enumerate() {
+            let index = candidate
+                .get("index")
+                .and_then(|i| i.as_u64())
+                .unwrap_or(pos as u64) as u32;
+
+            if let Some(parts) = candidate
+                .get("content")
+                .and_then(|c| c.get("parts"))
+                .and_then(|p| p.as_array())
+            {
+                for part_value in parts {
+                    if let Ok(part) = serde_json::from_value::<GeminiPart>(part_value.clone()) {
+                        chunks.extend(self.process_part(index, &part));
+                    }
+                }
+            }
+
+            if let Some(logprobs_result) = candidate.get("logprobsResult") {
+                let mut chunk = json!({
+                    "id": self.id,
+                    "object": "chat.completion.chunk",
+                    "created": self.created,
+                    "model": self.model,
+                    "choices": [{
+                        "index": index,
+                        "delta": {},
+                        "logprobs": to_openai_logprobs(logprobs_result),
+                        "finish_reason": null
+                    }]
+                });
+                if self.include_usage {
+                    chunk["usage"] = Value::Null;
+                }
+                chunks.push(self.emit(chunk));
+            }
+
+            if let Some(finish_reason) = candidate.get("finishReason").and_then(|f| f.as_str()) {
+                if self.finished.insert(index) {
+                    let reason = if self.tool_calls.contains_key(&index) && finish_reason == "STOP" {
+                        "tool_calls"
+                    } else {
+                        map_finish_reason(finish_reason)
+                    };
+                    chunks.push(self.emit_chunk(index, json!({}), Some(reason)));
+                }
+            }
+        }
+
+        chunks
+    }
+
+    /// 处理单个 part
+    fn process_part(&mut self, index: u32, part: &GeminiPart) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+
+        if let Some(text) = &part.text {
+            if text.is_empty() {
+                return chunks;
+            }
+            if part.thought.unwrap_or(false) {
+                chunks.push(self.emit_delta(index, json!({ "reasoning_content": text })));
+            } else {
+                chunks.push(self.emit_delta(index, json!({ "content": text })));
+            }
+        }
+
+        if let Some(img) = &part.inline_data {
+            if !img.data.is_empty() {
+                let markdown_img = format!("![image](data:{};base64,{})", img.mime_type, img.data);
+                chunks.push(self.emit_delta(index, json!({ "content": markdown_img })));
+            }
+        }
+
+        if let Some(fc) = &part.function_call {
+            // parallel_tool_calls: false: 丢弃第一个之后的 functionCall
+            if !self.parallel_tool_calls && self.tool_calls.contains_key(&index) {
+                tracing::debug!("[OpenAI-Stream] Dropping parallel function call '{}'", fc.name);
+                return chunks;
+            }
+
+            let call_id = fc
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}", crate::proxy::common::utils::generate_random_id()));
+
+            // Cache the signature so the next turn can restore it from the tool_call id
+            if let Some(sig) = &part.thought_signature {
+                SignatureCache::global().cache_tool_signature(&call_id, sig.clone());
+            }
+
+            let counter = self.tool_calls.entry(index).or_insert(0);
+            let call_index = *counter;
+            *counter += 1;
+
+            let arguments = fc
+                .args
+                .as_ref()
+                .map(|a| serde_json::to_string(a).unwrap_or_default())
+                .unwrap_or_else(|| "{}".to_string());
+
+            chunks.push(self.emit_delta(
+                index,
+                json!({
+                    "tool_calls": [{
+                        "index": call_index,
+                        "id": call_id,
+                        "type": "function",
+                        "function": {
+                            "name": fc.name,
+                            "arguments": arguments
+                        }
+                    }]
+                }),
+            ));
+        }
+
+        chunks
+    }
+
+    /// 发送结束事件: 缺失的 finish_reason, 可选 usage chunk, 以及 [DONE]
+    pub fn emit_finish(&mut self) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        if self.done_sent {
+            return chunks;
+        }
+
+        // Upstream closed without a finishReason - still terminate every open choice
+        let mut open: Vec<u32> = self.role_sent.difference(&self.finished).copied().collect();
+        if self.finished.is_empty() && open.is_empty() {
+            open.push(0);
+        }
+        open.sort_unstable();
+        for index in open {
+            self.finished.insert(index);
+            let reason = if self.tool_calls.contains_key(&index) { "tool_calls" } else { "stop" };
+            chunks.push(self.emit_chunk(index, json!({}), Some(reason)));
+        }
+
+        if self.include_usage {
+            let usage = self
+                .usage
+                .as_ref()
+                .map(to_openai_usage)
+                .unwrap_or_else(|| json!({ "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }));
+            chunks.push(self.emit(json!({
+                "id": self.id,
+                "object": "chat.completion.chunk",
+                "created": self.created,
+                "model": self.model,
+                "choices": [],
+                "usage": usage
+            })));
+        }
+
+        chunks.push(Bytes::from("data: [DONE]\n\n"));
+        self.done_sent = true;
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_text(chunks: &[Bytes]) -> String {
+        chunks
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_text_chunks_and_finish() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-flash", false);
+        let chunks = state.process_gemini_chunk(&json!({
+            "candidates": [{ "content": { "parts": [{ "text": "Hel" }] } }]
+        }));
+        let out = to_text(&chunks);
+        assert!(out.contains(r#""object":"chat.completion.chunk""#));
+        assert!(out.contains(r#""role":"assistant""#));
+        assert!(out.contains(r#""content":"Hel""#));
+
+        let chunks = state.process_gemini_chunk(&json!({
+            "candidates": [{ "content": { "parts": [{ "text": "lo" }] }, "finishReason": "MAX_TOKENS" }]
+        }));
+        let out = to_text(&chunks);
+        assert!(!out.contains(r#""role""#));
+        assert!(out.contains(r#""finish_reason":"length""#));
+
+        let out = to_text(&state.emit_finish());
+        assert!(out.ends_with("data: [DONE]\n\n"));
+        assert!(!out.contains("usage"));
+    }
+
+    #[test]
+    fn test_include_usage_chunk() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-flash", true);
+        state.process_gemini_chunk(&json!({
+            "candidates": [{ "content": { "parts": [{ "text": "Hi" }] }, "finishReason": "STOP" }],
+            "usageMetadata": { "promptTokenCount": 7, "candidatesTokenCount": 2, "totalTokenCount": 9 }
+        }));
+        let out = to_text(&state.emit_finish());
+        assert!(out.contains(r#""choices":[]"#));
+        assert!(out.contains(r#""prompt_tokens":7"#));
+        assert!(out.contains(r#""total_tokens":9"#));
+        assert!(!out.contains("reasoning_tokens"));
+        assert!(out.contains("[DONE]"));
+    }
+
+    #[test]
+    fn test_usage_counts_thinking_tokens() {
+        let usage: UsageMetadata = serde_json::from_value(json!({
+            "promptTokenCount": 10,
+            "candidatesTokenCount": 4,
+            "thoughtsTokenCount": 6,
+            "totalTokenCount": 20
+        }))
+        .unwrap();
+        let usage = to_openai_usage(&usage);
+        assert_eq!(usage["completion_tokens"], 10);
+        assert_eq!(usage["total_tokens"], 20);
+        assert_eq!(usage["completion_tokens_details"]["reasoning_tokens"], 6);
+    }
+
+    #[test]
+    fn test_logprobs_mapping() {
+        let logprobs = to_openai_logprobs(&json!({
+            "chosenCandidates": [{ "token": "Hi", "logProbability": -0.1 }],
+            "topCandidates": [{ "candidates": [
+                { "token": "Hi", "logProbability": -0.1 },
+                { "token": "Hello", "logProbability": -2.3 }
+            ] }]
+        }));
+        assert_eq!(logprobs["content"][0]["token"], "Hi");
+        assert_eq!(logprobs["content"][0]["logprob"], -0.1);
+        assert_eq!(logprobs["content"][0]["top_logprobs"][1]["token"], "Hello");
+    }
+
+    #[test]
+    fn test_function_call_chunks() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-pro", false);
+        let sig = "s".repeat(64);
+        let chunks = state.process_gemini_chunk(&json!({
+            "candidates": [{
+                "content": { "parts": [
+                    { "functionCall": { "name": "read", "id": "call_openai_stream_a", "args": { "path": "a" } }, "thoughtSignature": sig },
+                    { "functionCall": { "name": "read", "args": { "path": "b" } } }
+                ] },
+                "finishReason": "STOP"
+            }]
+        }));
+        let out = to_text(&chunks);
+        assert!(out.contains(r#""id":"call_openai_stream_a""#));
+        assert!(out.contains(r#""arguments":"{\"path\":\"a\"}""#));
+        assert!(out.contains(r#""index":1"#));
+        assert!(out.contains(r#""finish_reason":"tool_calls""#));
+        assert_eq!(
+            SignatureCache::global().get_tool_signature("call_openai_stream_a"),
+            Some(sig)
+        );
+    }
+
+    #[test]
+    fn test_parallel_tool_calls_disabled() {
+        let mut state = OpenAIStreamingState::new("gemini-2.5-pro", false).with_parallel_tool_calls(false);
+        let chunks = state.process_gemini_chunk(&json!({
+            "candidates": [{
+                "content": { "parts": [
+                    { "functionCall": { "name": "first_tool", "args": {} } },
+                    { "functionCall": { "name": "second_tool", "args": {} } }
+                ] },
+                "finishReason": "STOP"
+            }]
+        }));
+        let out = to_text(&chunks);
+        assert!(out.contains("first_tool"));
+        assert!(!out.contains("second_tool"));
+        assert!(out.contains(r#""finish_reason":"tool_calls""#));
+    }
+}
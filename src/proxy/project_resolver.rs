@@ -1,5 +1,21 @@
 use serde_json::Value;
 
+/// How long a resolved project_id is reused before calling loadCodeAssist again
+pub const PROJECT_ID_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Return the cached project_id for an account if it is still usable.
+/// A user-pinned override always wins and never expires.
+pub fn cached_project_id(account: &crate::config::account::Account, now: i64) -> Option<String> {
+    if let Some(pinned) = account.project_id_override.as_ref().filter(|p| !p.trim().is_empty()) {
+        return Some(pinned.trim().to_string());
+    }
+
+    match (&account.project_id, account.project_id_resolved_at) {
+        (Some(pid), Some(resolved_at)) if now - resolved_at < PROJECT_ID_TTL_SECS => Some(pid.clone()),
+        _ => None,
+    }
+}
+
 /// Get project_id using Antigravity's loadCodeAssist API
 pub async fn fetch_project_id(access_token: &str) -> Result<String, String> {
     // Use Sandbox environment to avoid Prod 429 errors
@@ -50,3 +66,53 @@ pub fn generate_mock_project_id() -> String {
     // Return the known working project ID from DroidGravity-Manager
     "bamboo-precept-lgxtn".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::account::{Account, TokenData};
+
+    fn account() -> Account {
+        Account {
+            id: "acc-1".to_string(),
+            email: "test@example.com".to_string(),
+            display_name: None,
+            token: TokenData::new("access".to_string(), "refresh".to_string(), 3600),
+            disabled: false,
+            created_at: 0,
+            updated_at: 0,
+            project_id: None,
+            project_id_resolved_at: None,
+            project_id_override: None,
+            label: None,
+            sort_order: 0,
+            needs_reauth: None,
+            priority: 0,
+            weight: 1,
+            groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_cached_project_id_ttl() {
+        let mut acc = account();
+        assert_eq!(cached_project_id(&acc, 1000), None);
+
+        acc.project_id = Some("proj-a".to_string());
+        acc.project_id_resolved_at = Some(1000);
+        assert_eq!(cached_project_id(&acc, 1000 + 60), Some("proj-a".to_string()));
+        assert_eq!(cached_project_id(&acc, 1000 + PROJECT_ID_TTL_SECS), None);
+    }
+
+    #[test]
+    fn test_project_id_override_wins() {
+        let mut acc = account();
+        acc.project_id = Some("proj-a".to_string());
+        acc.project_id_resolved_at = Some(0);
+        acc.project_id_override = Some("pinned-proj".to_string());
+        assert_eq!(cached_project_id(&acc, i64::MAX), Some("pinned-proj".to_string()));
+
+        acc.project_id_override = Some("  ".to_string());
+        assert_eq!(cached_project_id(&acc, 10), Some("proj-a".to_string()));
+    }
+}
@@ -1,43 +1,63 @@
 use anyhow::Result;
 use axum::{
     extract::{Json, State},
-    http::{StatusCode, HeaderMap},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
 use serde_json::{json, Value};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
+use super::account_pool::AccountPool;
 use super::config::ProxyConfig;
+use super::error::{client_status_for, ClientProtocol, UpstreamError, UpstreamErrorKind};
 
 const MAX_RETRY_ATTEMPTS: usize = 3;  // Reduced from 10 to avoid excessive retries
 
 #[derive(Clone)]
 struct AppState {
-    accounts: Arc<RwLock<Vec<crate::config::account::Account>>>,
-    current_account_index: Arc<RwLock<usize>>,
+    /// Accounts, selection, cooldowns and token refresh (hot reloaded)
+    pool: Arc<AccountPool>,
+    /// Models tried after the account pool is exhausted for the requested one
+    model_fallbacks: Arc<HashMap<String, Vec<String>>>,
 }
 
 pub async fn start_server(config: ProxyConfig) -> Result<()> {
     // Load accounts
-    let accounts = crate::config::account::list_accounts()?;
+    let pool = Arc::new(
+        AccountPool::load()?
+            .with_strategy(config.selection_strategy)
+            .with_routes(config.model_routes.clone()),
+    );
     
-    if accounts.is_empty() {
+    if pool.is_empty() {
         anyhow::bail!("No accounts configured. Add accounts first using 'drovity menu'");
     }
+    // Accounts added or removed from the menu are picked up without a restart
+    pool.spawn_watcher();
     
     let state = AppState {
-        accounts: Arc::new(RwLock::new(accounts)),
-        current_account_index: Arc::new(RwLock::new(0)),
+        pool: pool.clone(),
+        model_fallbacks: Arc::new(config.model_fallbacks.clone()),
     };
     
+    let auth_state = super::auth::AuthState::new(&config.api_key);
+    if !auth_state.is_enabled() {
+        tracing::warn!("⚠️ No API key configured - proxy routes are open to anonymous clients");
+    }
+    
     let app = Router::new()
         // OpenAI compatible endpoints
         .route("/v1/chat/completions", post(handle_chat_completions))
+        .route("/v1/responses", post(handle_responses))
         .route("/v1/messages", post(handle_anthropic_messages))
+        .route("/v1/messages/count_tokens", post(handle_count_tokens))
         .route("/v1/models", get(handle_list_models))
+        .route("/status", get(handle_status))
+        // Everything registered above requires the API key; /healthz stays open
+        .route_layer(middleware::from_fn_with_state(auth_state, super::auth::auth_middleware))
         .route("/healthz", get(health_check))
         .with_state(state);
     
@@ -45,7 +65,7 @@ pub async fn start_server(config: ProxyConfig) -> Result<()> {
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     
     tracing::info!("Proxy server started on http://{}", addr);
-    tracing::info!("Loaded {} account(s)", crate::config::account::list_accounts()?.len());
+    tracing::info!("Loaded {} account(s)", pool.len());
     
     axum::serve(listener, app).await?;
     
@@ -60,6 +80,15 @@ async fn health_check() -> Response {
     .into_response()
 }
 
+/// Daemon status for `drovity status`
+async fn handle_status(State(state): State<AppState>) -> Response {
+    Json(json!({
+        "accounts": state.pool.len(),
+        "cooldowns": state.pool.cooldowns().snapshot()
+    }))
+    .into_response()
+}
+
 async fn handle_list_models() -> Response {
     Json(json!({
         "object": "list",
@@ -93,7 +122,7 @@ async fn handle_list_models() -> Response {
     .into_response()
 }
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 async fn handle_chat_completions(
     State(state): State<AppState>,
@@ -102,16 +131,72 @@ async fn handle_chat_completions(
 ) -> Response {
     // Log incoming request
     tracing::info!("📥 Incoming chat completions request");
-    tracing::info!("   Headers: {:?}", headers.get("authorization").map(|h| h.to_str().unwrap_or("invalid")));
+    tracing::info!("   User-Agent: {}", headers.get("user-agent").and_then(|h| h.to_str().ok()).unwrap_or("unknown"));
     tracing::info!("   Model: {}", payload["model"].as_str().unwrap_or("not specified"));
     
-    // Factory Droid format conversion: input -> messages
-    if let Some(input) = payload.get("input").cloned() {
+    // Factory Droid sends Responses-shaped bodies (input items, flat tools) to chat.completions;
+    // reuse the Responses converter and answer in chat.completions format
+    if payload.get("input").is_some() {
         tracing::info!("   Converting Factory Droid 'input' to 'messages'");
-        payload["messages"] = convert_input_to_messages(input);
-        payload.as_object_mut().unwrap().remove("input");
+        payload = match super::openai::responses::transform_responses_request(&payload) {
+            Ok((chat_payload, _)) => chat_payload,
+            Err(e) => {
+                tracing::warn!("❌ Rejected chat completions request: {}", e);
+                return ClientProtocol::OpenAI.error_response(StatusCode::BAD_REQUEST, &e);
+            }
+        };
+    }
+    
+    if let Err(e) = super::openai::validate_request_params(&payload) {
+        tracing::warn!("❌ Rejected chat completions request: {}", e);
+        return ClientProtocol::OpenAI.error_response(StatusCode::BAD_REQUEST, &e);
+    }
+    
+    let session_key = super::openai::resolve_session_id(&payload, session_header(&headers));
+    dispatch_openai_request(state, payload, None, session_key).await
+}
+
+async fn handle_responses(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Response {
+    tracing::info!("📥 Incoming responses request");
+    tracing::info!("   User-Agent: {}", headers.get("user-agent").and_then(|h| h.to_str().ok()).unwrap_or("unknown"));
+    tracing::info!("   Model: {}", payload["model"].as_str().unwrap_or("not specified"));
+    
+    let (chat_payload, context) = match super::openai::responses::transform_responses_request(&payload) {
+        Ok(converted) => converted,
+        Err(e) => {
+            tracing::warn!("❌ Rejected responses request: {}", e);
+            return ClientProtocol::Responses.error_response(StatusCode::BAD_REQUEST, &e);
+        }
+    };
+    
+    if let Err(e) = super::openai::validate_request_params(&chat_payload) {
+        tracing::warn!("❌ Rejected responses request: {}", e);
+        return ClientProtocol::Responses.error_response(StatusCode::BAD_REQUEST, &e);
     }
     
+    let session_key = super::openai::resolve_session_id(&chat_payload, session_header(&headers));
+    dispatch_openai_request(state, chat_payload, Some(context), session_key).await
+}
+
+/// Explicit conversation id sent by the client
+fn session_header(headers: &HeaderMap) -> Option<&str> {
+    headers.get("x-drovity-session").and_then(|h| h.to_str().ok())
+}
+
+/// Send an OpenAI-style request upstream, rotating accounts on retryable errors.
+///
+/// `responses` is set for /v1/responses, whose output is built from the same
+/// Gemini stream as chat.completions.
+async fn dispatch_openai_request(
+    state: AppState,
+    payload: Value,
+    responses: Option<super::openai::responses::ResponsesContext>,
+    session_key: String,
+) -> Response {
     // Log messages
     if let Some(messages) = payload["messages"].as_array() {
         tracing::info!("   Messages count: {}", messages.len());
@@ -128,173 +213,312 @@ async fn handle_chat_completions(
     }
     
     // Get all accounts for retry loop
-    let accounts = state.accounts.read().await.clone();
-    let pool_size = accounts.len();
+    let pool_size = state.pool.len();
     // [FIX] Ensure at least 2 attempts if possible, to allow for rotation
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
     
+    let protocol = if responses.is_some() { ClientProtocol::Responses } else { ClientProtocol::OpenAI };
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
-    
-    // [FIX] Track failed accounts to strictly exclude them in retries
-    let mut failed_emails: HashSet<String> = HashSet::new();
-    
-    // Retry loop with account rotation
-    for attempt in 0..max_attempts {
-        let force_rotate = attempt > 0;
+    // Status of the most recent failure, reported if every attempt fails
+    let mut last_status = StatusCode::SERVICE_UNAVAILABLE;
+    
+    let requested_model = payload["model"].as_str().unwrap_or("gemini-2.5-flash");
+    let models = super::common::model_mapping::model_fallback_chain(
+        requested_model,
+        &map_model_to_gemini(requested_model),
+        &state.model_fallbacks,
+    );
+    
+    for (model_index, gemini_model) in models.iter().enumerate() {
+        let gemini_model = gemini_model.as_str();
+        if model_index > 0 {
+            tracing::warn!("⤵️ Account pool exhausted for {}, falling back to {}", models[model_index - 1], gemini_model);
+        }
         
-        // Select account (smart rotation with strict exclusion)
-        let account = {
-            let mut index_guard = state.current_account_index.write().await;
+        // [FIX] Track failed accounts to strictly exclude them in retries
+        // (quotas are per model, so every account gets another chance on a fallback)
+        let mut failed_emails: HashSet<String> = HashSet::new();
+        
+        // Retry loop with account rotation
+        for attempt in 0..max_attempts {
+            let force_rotate = attempt > 0;
             
-            // If rotating or if current account is already failed, move to next
-            let start_index = *index_guard;
-            let mut found_account = None;
+            // Select account (session affinity, then smart rotation with strict exclusion)
+            let account = state.pool.select(&session_key, &mut failed_emails, force_rotate, gemini_model);
             
-            // Try to find a non-failed account starting from current index
-            for i in 0..pool_size {
-                let idx = (start_index + i) % pool_size;
-                if let Some(acc) = accounts.get(idx) {
-                    if !failed_emails.contains(&acc.email) {
-                        // Found a candidate
-                        if i > 0 || force_rotate {
-                            // If we had to search (i > 0) or force_rotate is true, update global index
-                            *index_guard = idx;
-                            // If we just rotated to it, check if we need to rotate FURTHER 
-                            // (in case force_rotate was true but we landed on current).
-                            // But here we just want *an* available account.
-                        }
-                        found_account = Some(acc.clone());
-                        break;
-                    }
+            let account = match account {
+                Some(acc) => {
+                    tracing::info!("   Using account: {} (attempt {}/{})", acc.email, attempt + 1, max_attempts);
+                    acc
+                },
+                None => {
+                    // Nothing may serve this model (disabled, or not in its routed group): try the next one
+                    tracing::error!("❌ No accounts available for {}", gemini_model);
+                    last_error = format!("No accounts available for {}", gemini_model);
+                    last_status = StatusCode::SERVICE_UNAVAILABLE;
+                    break;
                 }
-            }
+            };
             
-            // If no fresh account found (all failed), just return None or last one
-            if found_account.is_none() && !accounts.is_empty() {
-                 tracing::warn!("⚠️ All accounts marked as failed locally. Resetting local blacklist for this request.");
-                 failed_emails.clear();
-                 // Pick current as fallback
-                 accounts.get(*index_guard).cloned()
-            } else {
-                found_account
-            }
-        };
-        
-        let account = match account {
-            Some(acc) => {
-                tracing::info!("   Using account: {} (attempt {}/{})", acc.email, attempt + 1, max_attempts);
-                acc
-            },
-            None => {
-                tracing::error!("❌ No accounts available");
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": "No accounts available"}))
-                ).into_response();
-            }
-        };
-        
-        // Check if token needs refresh
-        let token = match refresh_token_if_needed(&account).await {
-            Ok(t) => {
-                tracing::info!("✅ Token valid/refreshed");
-                t
-            },
-            Err(e) => {
-                tracing::error!("❌ Failed to refresh token: {}", e);
-                last_error = format!("Token refresh failed: {}", e);
-                last_email = Some(account.email.clone());
-                failed_emails.insert(account.email.clone()); // Mark as failed
-                continue; // Try next account
-            }
-        };
-        
-        // Get project_id for this account
-        let project_id = match super::project_resolver::fetch_project_id(&token).await {
-            Ok(pid) => {
-                tracing::info!("   Project ID: {}", pid);
-                pid
-            },
-            Err(e) => {
-                // [FIX] Already using mock fallback in server.rs, now backed by stable ID
-                tracing::warn!("   Failed to get project_id, using mock: {}", e);
-                super::project_resolver::generate_mock_project_id()
-            }
-        };
-        
-        // Forward to Gemini API
-        let model = payload["model"].as_str().unwrap_or("gemini-2.5-flash");
-        let gemini_model = map_model_to_gemini(model);
-        
-        tracing::info!("🔄 Forwarding to Gemini API");
-        tracing::info!("   Requested model: {}", model);
-        tracing::info!("   Gemini model: {}", gemini_model);
-        
-        match forward_to_gemini_stream(&token, &gemini_model, &project_id, &payload).await {
-            Ok(response) => {
-                tracing::info!("✅ Response received from Gemini");
-                return response;
-            },
-            Err(e) => {
-                last_error = e.to_string();
-                last_email = Some(account.email.clone());
-                
-                tracing::error!("❌ Gemini API error (attempt {}/{}): {}", attempt + 1, max_attempts, e);
-                
-                // Parse error to decide retry strategy
-                let error_msg = e.to_string();
-                
-                // Check for retryable errors
-                if error_msg.contains("429") || error_msg.contains("503") || error_msg.contains("500") || error_msg.contains("RESOURCE_EXHAUSTED") {
-                    tracing::warn!("   Retryable error detected, rotating to next account");
-                    failed_emails.insert(account.email.clone()); // [FIX] Strictly exclude this account
-                    continue; // Retry with next account
+            // Check if token needs refresh
+            let token = match state.pool.access_token(&account).await {
+                Ok(t) => {
+                    tracing::info!("✅ Token valid/refreshed");
+                    t
+                },
+                Err(e) => {
+                    tracing::error!("❌ Failed to refresh token: {}", e);
+                    last_error = format!("Token refresh failed: {}", e);
+                    last_status = StatusCode::BAD_GATEWAY;
+                    last_email = Some(account.email.clone());
+                    state.pool.record_error(&account.id);
+                    failed_emails.insert(account.email.clone()); // Mark as failed
+                    continue; // Try next account
                 }
-                
-                // Check for quota exhausted (stop retrying)
-                if error_msg.contains("QUOTA_EXHAUSTED") {
-                    tracing::error!("   Quota exhausted - rotating"); // [FIX] Quota exhausted SHOULD rotate
-                    failed_emails.insert(account.email.clone());
-                    continue;
+            };
+            
+            // Get project_id for this account (cached per account)
+            let project_id = state.pool.project_id(&account, &token).await;
+            tracing::info!("   Project ID: {}", project_id);
+            
+            // Forward to Gemini API
+            tracing::info!("🔄 Forwarding to Gemini API");
+            tracing::info!("   Requested model: {}", requested_model);
+            tracing::info!("   Gemini model: {}", gemini_model);
+            
+            // Convert OpenAI format to Gemini envelope format
+            let mut gemini_payload = match super::openai::transform_openai_request(&payload, gemini_model, &project_id) {
+                Ok(p) => p,
+                Err(e) => {
+                    tracing::error!("❌ OpenAI→Gemini conversion error: {}", e);
+                    return protocol.error_response(StatusCode::BAD_REQUEST, &e);
                 }
+            };
+            let mut thinking_downgraded = false;
+            
+            let result = loop {
+                let trace_id = format!("req_{}", uuid::Uuid::new_v4());
+                let result = forward_to_gemini_stream(&token, gemini_model, &gemini_payload, &payload, trace_id, account.email.clone(), responses.clone()).await;
                 
-                // Check for auth errors
-                if error_msg.contains("401") || error_msg.contains("403") {
-                    tracing::warn!("   Auth error, trying next account");
+                // [FIX] Signature rejected -> strip thinking and retry once on the same account
+                match result {
+                    Err(e) if !thinking_downgraded && is_thinking_signature_rejection(&e) => {
+                        tracing::warn!(
+                            "[Thinking-Downgrade] Upstream rejected thinking signatures (account: {}, session: {}), retrying without thinking: {}",
+                            account.email, session_key, e
+                        );
+                        super::claude::request::downgrade_thinking(&mut gemini_payload);
+                        thinking_downgraded = true;
+                    }
+                    result => break result,
+                }
+            };
+            
+            match result {
+                Ok(response) => {
+                    tracing::info!("✅ Response received from Gemini");
+                    let response = with_model_header(response, gemini_model);
+                    return with_affinity_headers(response, &session_key, &account.email);
+                },
+                Err(e) => {
+                    last_error = e.to_string();
+                    last_email = Some(account.email.clone());
+                    
+                    tracing::error!("❌ Gemini API error (attempt {}/{}): {}", attempt + 1, max_attempts, e);
+                    
+                    last_status = client_status_for(&e);
+                    if let Some(response) = handle_attempt_error(&state, &account, gemini_model, &e, protocol).await {
+                        return with_affinity_headers(response, &session_key, &account.email);
+                    }
+                    
+                    // [FIX] Strictly exclude this account and retry with the next one
                     failed_emails.insert(account.email.clone());
                     continue;
                 }
-                
-                // Non-retryable error
-                if error_msg.contains("404") || error_msg.contains("400") {
-                    tracing::error!("   Non-retryable error, returning immediately");
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(json!({"error": error_msg}))
-                    ).into_response();
-                }
-                
-                // Generic error - retry
-                failed_emails.insert(account.email.clone());
-                continue;
             }
         }
     }
     
     // All attempts failed
     tracing::error!("❌ All {} attempts failed. Last error: {}", max_attempts, last_error);
-    (
-        StatusCode::TOO_MANY_REQUESTS,
-        Json(json!({
-            "error": format!("All attempts failed. Last error: {}", last_error),
-            "last_account": last_email.unwrap_or_else(|| "unknown".to_string())
-        }))
-    ).into_response()
+    protocol.error_response(
+        last_status,
+        &format!(
+            "All attempts failed (last account: {}). Last error: {}",
+            last_email.unwrap_or_else(|| "unknown".to_string()),
+            last_error
+        ),
+    )
+}
+
+/// 400 caused by thinking signatures upstream no longer accepts (retry without thinking)
+fn is_thinking_signature_rejection(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<UpstreamError>()
+        .is_some_and(|e| e.status == 400 && super::claude::request::is_thinking_signature_error(&e.body))
+}
+
+/// Apply the side effects of a failed attempt (project cache, cooldown) and decide
+/// whether another account may succeed.
+///
+/// Returns the response to send when retrying cannot help; `None` means rotate.
+async fn handle_attempt_error(
+    state: &AppState,
+    account: &crate::config::account::Account,
+    model: &str,
+    error: &anyhow::Error,
+    protocol: ClientProtocol,
+) -> Option<Response> {
+    // Transport and stream failures are not classified; rotate
+    let Some(upstream) = error.downcast_ref::<UpstreamError>() else {
+        state.pool.record_error(&account.id);
+        return None;
+    };
+    
+    // Only a denied or missing project means the cached project_id is stale
+    if upstream.is_project_error() {
+        tracing::warn!("   Project rejected for {}, re-resolving on next use", account.email);
+        state.pool.invalidate_project_id(&account.id);
+    }
+    
+    if let Some(delay) = upstream.cooldown() {
+        let reason = match upstream.kind() {
+            UpstreamErrorKind::QuotaExhausted => "quota exhausted",
+            _ => "rate limited",
+        };
+        state.pool.cooldowns().set(&account.id, &account.email, model, delay, reason);
+    }
+    
+    match upstream.kind() {
+        UpstreamErrorKind::RateLimited | UpstreamErrorKind::Server => {
+            tracing::warn!("   Retryable error ({:?}), rotating to next account", upstream.kind());
+        }
+        UpstreamErrorKind::QuotaExhausted => tracing::error!("   Quota exhausted - rotating"),
+        UpstreamErrorKind::Auth => tracing::warn!("   Auth error, trying next account"),
+        UpstreamErrorKind::Other => tracing::warn!("   Unclassified error, trying next account"),
+        UpstreamErrorKind::InvalidRequest | UpstreamErrorKind::NotFound => {}
+    }
+    
+    if upstream.should_rotate() {
+        state.pool.record_error(&account.id);
+        return None;
+    }
+    
+    tracing::error!("   Non-retryable error, returning immediately");
+    Some(protocol.error_response(upstream.client_status(), &error.to_string()))
+}
+
+/// Report which model actually served the request (differs from the requested one after fallback)
+fn with_model_header(mut response: Response, model: &str) -> Response {
+    if let Ok(value) = HeaderValue::from_str(model) {
+        response.headers_mut().insert("x-drovity-model", value);
+    }
+    response
+}
+
+/// Expose which account served the request, for debugging session affinity
+fn with_affinity_headers(mut response: Response, session_key: &str, email: &str) -> Response {
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(email) {
+        headers.insert("x-drovity-account", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(session_key) {
+        headers.insert("x-drovity-session", value);
+    }
+    response
+}
+
+/// Anthropic /v1/messages/count_tokens
+///
+/// Counts the transformed Gemini request (system prompt and tools included) with
+/// upstream countTokens, falling back to a local estimate if that fails.
+async fn handle_count_tokens(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(claude_payload): Json<Value>,
+) -> Response {
+    tracing::info!("📥 [CLAUDE/Anthropic] Incoming count_tokens request");
+    
+    let mut claude_request: super::claude::models::ClaudeRequest = match serde_json::from_value(claude_payload) {
+        Ok(r) => r,
+        Err(e) => {
+            return ClientProtocol::Anthropic.error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Failed to parse Claude request: {}", e),
+            );
+        }
+    };
+    super::claude::close_tool_loop_for_thinking(&mut claude_request.messages);
+    
+    // Project id is irrelevant for counting, the envelope is not sent
+    let gemini_payload = match super::claude::transform_claude_request_in(&claude_request, "") {
+        Ok(p) => p,
+        Err(e) => {
+            return ClientProtocol::Anthropic.error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Claude→Gemini conversion error: {}", e),
+            );
+        }
+    };
+    
+    // Same account the conversation's next message would use (routes, cooldowns, strategy)
+    let session_key = super::claude::request::resolve_session_id(&claude_request, session_header(&headers));
+    let gemini_model = super::common::model_mapping::map_claude_model_to_gemini(&claude_request.model);
+    let account = state.pool.peek(&session_key, &gemini_model);
+    
+    let upstream = match account {
+        Some(account) => match state.pool.access_token(&account).await {
+            Ok(token) => count_tokens_upstream(&token, &gemini_payload).await,
+            Err(e) => Err(e),
+        },
+        None => Err(anyhow::anyhow!("No accounts available")),
+    };
+    
+    let input_tokens = match upstream {
+        Ok(count) => count,
+        Err(e) => {
+            let estimate = super::claude::token_count::estimate_input_tokens(&gemini_payload);
+            tracing::warn!("   countTokens unavailable ({}), using local estimate: {}", e, estimate);
+            estimate
+        }
+    };
+    
+    Json(json!({ "input_tokens": input_tokens })).into_response()
+}
+
+async fn count_tokens_upstream(token: &str, gemini_payload: &Value) -> Result<u64> {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?;
+    
+    let url = "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal:countTokens";
+    let body = super::claude::token_count::build_count_tokens_request(gemini_payload);
+    
+    let response = client
+        .post(url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("User-Agent", crate::constants::USER_AGENT.as_str())
+        .header("Content-Type", "application/json")
+        .json(&body)
+        .send()
+        .await?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await?;
+        anyhow::bail!("countTokens error {}: {}", status, error_text);
+    }
+    
+    let result: Value = response.json().await?;
+    result["totalTokens"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("countTokens response missing totalTokens"))
 }
 
 async fn handle_anthropic_messages(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(claude_payload): Json<Value>,
 ) -> Response {
     tracing::info!("📥 [CLAUDE/Anthropic] Incoming request");
@@ -304,220 +528,163 @@ async fn handle_anthropic_messages(
     let gemini_model = map_model_to_gemini(model);
     tracing::info!("   Mapped to Gemini model: {}", gemini_model);
     
+    // DEBUG: Log incoming payload BEFORE parsing
+    tracing::debug!("🔍 RAW Claude payload: {}", serde_json::to_string_pretty(&claude_payload).unwrap_or_else(|_| "Failed to serialize".to_string()));
+    
+    // FULL CONVERSION: Parse Claude request into typed structure
+    let mut claude_request: super::claude::models::ClaudeRequest = match serde_json::from_value(claude_payload.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            let error = format!("Failed to parse Claude request: {}", e);
+            tracing::error!("❌ Deserialization error: {}", error);
+            return ClientProtocol::Anthropic.error_response(StatusCode::BAD_REQUEST, &error);
+        }
+    };
+    
+    // [FIX] Self-Healing: Recover from broken tool loops
+    // If thinking signatures were invalid and stripped by the client (or previous turns),
+    // we might have a ToolResult without a preceding Thinking block.
+    // This function injects synthetic messages to close the loop gracefully.
+    super::claude::close_tool_loop_for_thinking(&mut claude_request.messages);
+    
+    // Session key: isolates thought signatures and pins the conversation to one account
+    let session_key = super::claude::request::resolve_session_id(&claude_request, session_header(&headers));
+    
     // Account selection and retry logic
-    let accounts = state.accounts.read().await;
-    let pool_size = accounts.len();
+    let pool_size = state.pool.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
     
     let mut last_error = String::new();
     let mut last_email = None;
-    
-    // [FIX] Strict exclusion list
-    let mut failed_emails: HashSet<String> = HashSet::new();
-    
-    for attempt in 0..max_attempts {
-        let force_rotate = attempt > 0;
+    // Status of the most recent failure, reported if every attempt fails
+    let mut last_status = StatusCode::SERVICE_UNAVAILABLE;
+    
+    let models = super::common::model_mapping::model_fallback_chain(
+        &claude_request.model,
+        &super::common::model_mapping::map_claude_model_to_gemini(&claude_request.model),
+        &state.model_fallbacks,
+    );
+    
+    for (model_index, gemini_model) in models.iter().enumerate() {
+        let gemini_model = gemini_model.as_str();
+        if model_index > 0 {
+            tracing::warn!("⤵️ Account pool exhausted for {}, falling back to {}", models[model_index - 1], gemini_model);
+            // Fallback entries are upstream model names, which the transform passes through
+            claude_request.model = gemini_model.to_string();
+        }
+        
+        // [FIX] Strict exclusion list (per model: quotas are per model)
+        let mut failed_emails: HashSet<String> = HashSet::new();
         
-        // Select account (smart rotation with strict exclusion)
-        let account = {
-            let mut index_guard = state.current_account_index.write().await;
+        for attempt in 0..max_attempts {
+            let force_rotate = attempt > 0;
             
-            // Try to find a non-failed account starting from current index
-            let start_index = *index_guard;
-            let mut found_account = None;
+            // Select account (session affinity, then smart rotation with strict exclusion)
+            let account = state.pool.select(&session_key, &mut failed_emails, force_rotate, gemini_model);
             
-            for i in 0..pool_size {
-                let idx = (start_index + i) % pool_size;
-                if let Some(acc) = accounts.get(idx) {
-                    if !failed_emails.contains(&acc.email) {
-                        if i > 0 || force_rotate {
-                            *index_guard = idx;
-                        }
-                        found_account = Some(acc.clone());
-                        break;
-                    }
+            let account = match account {
+                Some(acc) => acc,
+                None => {
+                    // Nothing may serve this model (disabled, or not in its routed group): try the next one
+                    tracing::error!("❌ No accounts available for {}", gemini_model);
+                    last_error = format!("No accounts available for {}", gemini_model);
+                    last_status = StatusCode::SERVICE_UNAVAILABLE;
+                    break;
                 }
-            }
+            };
             
-            // If all excluded, reset local list
-            if found_account.is_none() && !accounts.is_empty() {
-                 failed_emails.clear();
-                 accounts.get(*index_guard).cloned()
-            } else {
-                found_account
-            }
-        };
-        
-        let account = match account {
-            Some(acc) => acc,
-            None => {
-                return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(json!({"error": "No accounts available"}))
-                ).into_response();
-            }
-        };
-        
-        tracing::info!("   Account: {} (attempt {}/{})", account.email, attempt + 1, max_attempts);
-        last_email = Some(account.email.clone());
-        
-        // Get token
-        let token = match refresh_token_if_needed(&account).await {
-            Ok(t) => {
-                tracing::info!("✅ Token OK");
-                t
-            },
-            Err(e) => {
-                last_error = e.to_string();
-                tracing::error!("❌ Token error: {}", e);
-                failed_emails.insert(account.email.clone());
-                continue;
-            }
-        };
-        
-        // Get project ID
-        let project_id = match super::project_resolver::fetch_project_id(&token).await {
-            Ok(pid) => {
-                tracing::info!("   Project: {}", pid);
-                pid
-            },
-            Err(e) => {
-                tracing::warn!("   Project fetch failed, using mock: {}", e);
-                super::project_resolver::generate_mock_project_id()
-            }
-        };
-        
-        
-        // DEBUG: Log incoming payload BEFORE parsing
-        tracing::debug!("🔍 RAW Claude payload: {}", serde_json::to_string_pretty(&claude_payload).unwrap_or_else(|_| "Failed to serialize".to_string()));
-        
-        // FULL CONVERSION: Parse Claude request into typed structure
-        let mut claude_request: super::claude::models::ClaudeRequest = match serde_json::from_value(claude_payload.clone()) {
-            Ok(r) => r,
-            Err(e) => {
-                last_error = format!("Failed to parse Claude request: {}", e);
-                tracing::error!("❌ Deserialization error: {}", last_error);
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": last_error}))
-                ).into_response();
-            }
-        };
-        
-        // [FIX] Self-Healing: Recover from broken tool loops
-        // If thinking signatures were invalid and stripped by the client (or previous turns),
-        // we might have a ToolResult without a preceding Thinking block.
-        // This function injects synthetic messages to close the loop gracefully.
-        super::claude::close_tool_loop_for_thinking(&mut claude_request.messages);
-        
-        // Convert using FULL DroidGravity-Manager logic
-        let gemini_payload = match super::claude::transform_claude_request_in(&claude_request, &project_id) {
-            Ok(p) => p,
-            Err(e) => {
-                last_error = format!("Claude→Gemini conversion error: {}", e);
-                tracing::error!("❌ {}", last_error);
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": last_error}))
-                ).into_response();
-            }
-        };
-        
-        
-        // Forward to Gemini (DIRECT - payload already in Gemini format!)
-        let stream_requested = claude_payload.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
-        let trace_id = format!("req_{}", uuid::Uuid::new_v4());
-        
-        match send_gemini_payload_direct(&token, &gemini_payload, stream_requested, trace_id, account.email.clone()).await {
-            Ok(response) => {
-                // Stream processing is done inside send_gemini_payload_direct
-                // Just return the response as-is (either SSE stream or collected JSON)
-                tracing::info!("✅ Response ready");
-                return response;
-            },
-            Err(e) => {
-                last_error = e.to_string();
-                tracing::error!("❌ Gemini API error (attempt {}/{}): {}", attempt + 1, max_attempts, e);
-                
-                let error_msg = e.to_string();
-                
-                // Retryable: 429, 500, 503
-                if error_msg.contains("429") || error_msg.contains("503") || error_msg.contains("500") || error_msg.contains("RESOURCE_EXHAUSTED") {
-                    tracing::warn!("   Retryable error → next account");
+            tracing::info!("   Account: {} (attempt {}/{})", account.email, attempt + 1, max_attempts);
+            last_email = Some(account.email.clone());
+            
+            // Get token
+            let token = match state.pool.access_token(&account).await {
+                Ok(t) => {
+                    tracing::info!("✅ Token OK");
+                    t
+                },
+                Err(e) => {
+                    last_error = e.to_string();
+                    last_status = StatusCode::BAD_GATEWAY;
+                    tracing::error!("❌ Token error: {}", e);
+                    state.pool.record_error(&account.id);
                     failed_emails.insert(account.email.clone());
                     continue;
                 }
-                
-                // Non-retryable: 400, 404
-                if error_msg.contains("404") || error_msg.contains("400") {
-                    tracing::error!("   Non-retryable error");
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(json!({"error": error_msg}))
-                    ).into_response();
+            };
+            
+            // Get project ID (cached per account)
+            let project_id = state.pool.project_id(&account, &token).await;
+            tracing::info!("   Project: {}", project_id);
+            
+            
+            // Convert using FULL DroidGravity-Manager logic
+            let gemini_payload = match super::claude::request::transform_claude_request_for_session(&claude_request, &project_id, &session_key) {
+                Ok(p) => p,
+                Err(e) => {
+                    last_error = format!("Claude→Gemini conversion error: {}", e);
+                    tracing::error!("❌ {}", last_error);
+                    return ClientProtocol::Anthropic.error_response(StatusCode::BAD_REQUEST, &last_error);
                 }
+            };
+            
+            
+            // Forward to Gemini (DIRECT - payload already in Gemini format!)
+            let stream_requested = claude_payload.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+            let mut gemini_payload = gemini_payload;
+            let mut thinking_downgraded = false;
+            
+            let result = loop {
+                let trace_id = format!("req_{}", uuid::Uuid::new_v4());
+                let result = send_gemini_payload_direct(&token, &gemini_payload, stream_requested, trace_id, account.email.clone(), super::claude::StreamOptions::from_request(&claude_request, &session_key)).await;
                 
-                failed_emails.insert(account.email.clone());
-                continue;
+                // [FIX] Signature rejected -> strip thinking and retry once on the same account
+                match result {
+                    Err(e) if !thinking_downgraded && is_thinking_signature_rejection(&e) => {
+                        tracing::warn!(
+                            "[Thinking-Downgrade] Upstream rejected thinking signatures (account: {}, session: {}), retrying without thinking: {}",
+                            account.email, session_key, e
+                        );
+                        super::claude::request::downgrade_thinking(&mut gemini_payload);
+                        thinking_downgraded = true;
+                    }
+                    result => break result,
+                }
+            };
+            
+            match result {
+                Ok(response) => {
+                    // Stream processing is done inside send_gemini_payload_direct
+                    // Just return the response as-is (either SSE stream or collected JSON)
+                    tracing::info!("✅ Response ready");
+                    let response = with_model_header(response, gemini_model);
+                    return with_affinity_headers(response, &session_key, &account.email);
+                },
+                Err(e) => {
+                    last_error = e.to_string();
+                    tracing::error!("❌ Gemini API error (attempt {}/{}): {}", attempt + 1, max_attempts, e);
+                    
+                    last_status = client_status_for(&e);
+                    if let Some(response) = handle_attempt_error(&state, &account, gemini_model, &e, ClientProtocol::Anthropic).await {
+                        return with_affinity_headers(response, &session_key, &account.email);
+                    }
+                    
+                    failed_emails.insert(account.email.clone());
+                    continue;
+                }
             }
         }
     }
     
     // All attempts failed
     tracing::error!("❌ All {} attempts failed. Last: {}", max_attempts, last_error);
-    (
-        StatusCode::TOO_MANY_REQUESTS,
-        Json(json!({
-            "error": format!("All attempts failed. Last: {}", last_error),
-            "last_account": last_email.unwrap_or_else(|| "unknown".to_string())
-        }))
-    ).into_response()
-}
-
-fn convert_input_to_messages(input: Value) -> Value {
-    if let Some(array) = input.as_array() {
-        let messages: Vec<Value> = array.iter().map(|msg| {
-            let role = msg["role"].as_str().unwrap_or("user");
-            let content = if let Some(content_array) = msg["content"].as_array() {
-                // Extract text from content blocks
-                let text_parts: Vec<String> = content_array.iter()
-                    .filter_map(|block| {
-                        if block["type"] == "input_text" {
-                            block["text"].as_str().map(|s| s.to_string())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                json!(text_parts.join("\n"))
-            } else {
-                msg["content"].clone()
-            };
-            
-            json!({
-                "role": role,
-                "content": content
-            })
-        }).collect();
-        
-        json!(messages)
-    } else {
-        json!([])
-    }
-}
-
-async fn refresh_token_if_needed(account: &crate::config::account::Account) -> Result<String> {
-    use chrono::Utc;
-    
-    let now = Utc::now().timestamp();
-    
-    // If token expires in less than 5 minutes, refresh
-    if account.token.expiry_timestamp < now + 300 {
-        let token_response = crate::oauth::refresh_access_token(&account.token.refresh_token).await?;
-        Ok(token_response.access_token)
-    } else {
-        Ok(account.token.access_token.clone())
-    }
+    ClientProtocol::Anthropic.error_response(
+        last_status,
+        &format!(
+            "All attempts failed (last account: {}). Last: {}",
+            last_email.unwrap_or_else(|| "unknown".to_string()),
+            last_error
+        ),
+    )
 }
 
 fn map_model_to_gemini(model: &str) -> String {
@@ -568,6 +735,13 @@ fn map_model_to_gemini(model: &str) -> String {
     }
 }
 
+fn retry_after_header(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
 // [COPY FROM ORIGINAL] Direct Gemini API caller with stream processing
 // Matches DroidGravity-Manager's logic: bytes_stream -> create_claude_sse_stream -> collect_stream_to_json
 async fn send_gemini_payload_direct(
@@ -576,6 +750,7 @@ async fn send_gemini_payload_direct(
     stream_requested: bool,
     trace_id: String,
     email: String,
+    options: super::claude::StreamOptions,
 ) -> Result<Response> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(300))
@@ -599,9 +774,10 @@ async fn send_gemini_payload_direct(
     tracing::info!("   Response status: {}", status);
     
     if !status.is_success() {
+        let retry_after = retry_after_header(response.headers());
         let error_text = response.text().await?;
         tracing::error!("❌ Gemini API error: {}", error_text);
-        anyhow::bail!("Gemini API error {}: {}", status, error_text);
+        return Err(UpstreamError::from_response(status.as_u16(), retry_after.as_deref(), &error_text).into());
     }
     
     // ORIGINAL LOGIC: bytes_stream -> create_claude_sse_stream
@@ -609,7 +785,7 @@ async fn send_gemini_payload_direct(
     
     let stream = response.bytes_stream();
     let gemini_stream = Box::pin(stream);
-    let claude_stream = super::claude::create_claude_sse_stream(gemini_stream, trace_id.clone(), email.clone());
+    let claude_stream = super::claude::create_claude_sse_stream(gemini_stream, trace_id.clone(), email.clone(), options);
     
     // Client wants JSON (non-stream) - collect the stream
     if !stream_requested {
@@ -618,7 +794,7 @@ async fn send_gemini_payload_direct(
         
         // Convert Stream<Result<Bytes, String>> to Stream<Result<Bytes, io::Error>>
         let converted_stream = claude_stream.map(|result| {
-            result.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            result.map_err(std::io::Error::other)
         });
         
         match collect_stream_to_json(Box::pin(converted_stream)).await {
@@ -647,185 +823,157 @@ async fn send_gemini_payload_direct(
 }
 
 // Use STREAM for better quota (like DroidGravity-Manager)
-async fn forward_to_gemini_stream(token: &str, model: &str, project_id: &str, payload: &Value) -> Result<Response> {
+// bytes_stream -> create_openai_sse_stream -> (SSE | collect_stream_to_json)
+async fn forward_to_gemini_stream(
+    token: &str,
+    model: &str,
+    gemini_payload: &Value,
+    payload: &Value,
+    trace_id: String,
+    email: String,
+    responses: Option<super::openai::responses::ResponsesContext>,
+) -> Result<Response> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(300))
         .build()?;
     
-    // Convert OpenAI format to Gemini envelope format  
-    let gemini_payload = convert_to_gemini_format(payload, model, project_id)?;
+    let stream_requested = payload.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+    let include_usage = payload
+        .pointer("/stream_options/include_usage")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let parallel_tool_calls = payload["parallel_tool_calls"].as_bool().unwrap_or(true);
     
     // Use streamGenerateContent for better quota
     let url = "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal:streamGenerateContent?alt=sse";
     
-    tracing::info!("   POST {} (STREAM)", url);
-    let payload_string = serde_json::to_string(&gemini_payload)?;
-    tracing::info!("   Payload size: {} bytes", payload_string.len());
-    
-    // CRITICAL: Log full payload for troubleshooting
-    tracing::info!("   📤 SENDING PAYLOAD: {}", serde_json::to_string_pretty(&gemini_payload)?);
-    
-    
-    let response = client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("User-Agent", crate::constants::USER_AGENT.as_str())
-        .header("Content-Type", "application/json")
-        .json(&gemini_payload)
-        .send()
-        .await?;
-    
-    let status = response.status();
-    tracing::info!("   Response status: {}", status);
-    
-    if !status.is_success() {
-        let error_text = response.text().await?;
-        tracing::error!("❌ Gemini API error response: {}", error_text);
-        anyhow::bail!("Gemini API error {}: {}", status, error_text);
-    }
-    
-    // Collect SSE stream into single response
-    let stream_body = response.text().await?;
-    tracing::info!("   Stream received: {} bytes", stream_body.len());
-    
-    // Parse SSE and collect final response
-    let gemini_response = parse_sse_stream(&stream_body)?;
+    // Structured output is checked on the collected response, streamed output goes out as is
+    let expected_schema = if stream_requested {
+        None
+    } else {
+        super::openai::expected_output_schema(payload).ok().flatten()
+    };
+    let mut validation_retried = false;
     
-    // Extract from envelope
-    let response_data = gemini_response.get("response").unwrap_or(&gemini_response);
+    tracing::info!("   POST {} (stream={})", url, stream_requested);
+    let payload_string = serde_json::to_string(gemini_payload)?;
+    tracing::info!("   Payload size: {} bytes", payload_string.len());
+    tracing::debug!("   📤 Gemini payload: {}", serde_json::to_string_pretty(gemini_payload)?);
     
-    // Log first part of content
-    if let Some(text) = response_data["candidates"][0]["content"]["parts"][0]["text"].as_str() {
-        let preview = if text.len() > 200 {
-            format!("{}...", &text[..200])
-        } else {
-            text.to_string()
+    loop {
+        let response = client
+            .post(url)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("User-Agent", crate::constants::USER_AGENT.as_str())
+            .header("Content-Type", "application/json")
+            .json(gemini_payload)
+            .send()
+            .await?;
+        
+        let status = response.status();
+        tracing::info!("   Response status: {}", status);
+        
+        if !status.is_success() {
+            let retry_after = retry_after_header(response.headers());
+            let error_text = response.text().await?;
+            tracing::error!("❌ Gemini API error response: {}", error_text);
+            return Err(UpstreamError::from_response(status.as_u16(), retry_after.as_deref(), &error_text).into());
+        }
+        
+        use axum::body::Body;
+        
+        let gemini_stream = Box::pin(response.bytes_stream());
+        
+        if let Some(context) = responses {
+            return forward_responses_output(gemini_stream, trace_id, email, model, context, stream_requested).await;
+        }
+        
+        // Non-stream clients always get usage in the collected response
+        let openai_stream = super::openai::create_openai_sse_stream(
+            gemini_stream,
+            trace_id.clone(),
+            email.clone(),
+            model.to_string(),
+            include_usage || !stream_requested,
+            parallel_tool_calls,
+        );
+        
+        if stream_requested {
+            return Ok(axum::response::Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/event-stream")
+                .header("Cache-Control", "no-cache")
+                .header("Connection", "keep-alive")
+                .header("X-Account-Email", &email)
+                .body(Body::from_stream(openai_stream))
+                .unwrap());
+        }
+        
+        use futures::StreamExt;
+        
+        let converted_stream = openai_stream.map(|result| {
+            result.map_err(std::io::Error::other)
+        });
+        
+        let full_response = match super::openai::collect_stream_to_json(Box::pin(converted_stream)).await {
+            Ok(full_response) => full_response,
+            Err(e) => anyhow::bail!("Stream collection error: {}", e),
         };
-        tracing::info!("   Content preview: {}", preview);
-    }
-    
-    // Convert Gemini response back to OpenAI format
-    let openai_response = convert_gemini_to_openai_response(response_data, model)?;
-    
-    Ok(Json(openai_response).into_response())
-}
-
-fn parse_sse_stream(stream_body: &str) -> Result<Value> {
-    let mut accumulated_text = String::new();
-    
-    // Parse SSE events
-    for line in stream_body.lines() {
-        if line.starts_with("data: ") {
-            let data = &line[6..]; // Remove "data: " prefix
-            if data.trim() == "[DONE]" {
-                continue;
-            }
-            
-            if let Ok(event) = serde_json::from_str::<Value>(data) {
-                // Extract text from streaming chunks
-                if let Some(text) = event["response"]["candidates"][0]["content"]["parts"][0]["text"].as_str() {
-                    accumulated_text.push_str(text);
-                } else if let Some(text) = event["candidates"][0]["content"]["parts"][0]["text"].as_str() {
-                    accumulated_text.push_str(text);
+        tracing::info!("✅ Stream collected to JSON");
+        
+        if let Some(schema) = &expected_schema {
+            if let Err(e) = super::openai::validate_structured_output(&full_response, schema) {
+                if !validation_retried {
+                    tracing::warn!("   Structured output does not match response_format ({}), retrying once", e);
+                    validation_retried = true;
+                    continue;
                 }
+                tracing::warn!("   Structured output still invalid after retry: {}", e);
             }
         }
-    }
-    
-    // Build final response structure
-    Ok(json!({
-        "candidates": [{
-            "content": {
-                "parts": [{
-                    "text": accumulated_text
-                }]
-            }
-        }]
-    }))
-}
-
-fn convert_to_gemini_format(payload: &Value, model: &str, project_id: &str) -> Result<Value> {
-    let messages = payload["messages"].as_array()
-        .ok_or_else(|| anyhow::anyhow!("Missing messages field"))?;
-    
-    let contents: Vec<Value> = messages.iter().filter(|msg| {
-        msg["role"].as_str().unwrap_or("user") != "system"
-    }).map(|msg| {
-        let role = match msg["role"].as_str().unwrap_or("user") {
-            "assistant" => "model",
-            role => role,
-        };
         
-        json!({
-            "role": role,
-            "parts": [{
-                "text": msg["content"].as_str().unwrap_or("")
-            }]
-        })
-    }).collect();
-    
-    // Extract system message for systemInstruction
-    let system_text: Vec<String> = messages.iter()
-        .filter(|msg| msg["role"].as_str() == Some("system"))
-        .filter_map(|msg| msg["content"].as_str().map(|s| s.to_string()))
-        .collect();
-    
-    let inner_request = json!({
-        "contents": contents,
-        "generationConfig": {
-            "maxOutputTokens": payload.get("max_tokens").unwrap_or(&json!(8192)),
-            "temperature": payload.get("temperature").unwrap_or(&json!(1.0)),
-        },
-        "systemInstruction": if !system_text.is_empty() {
-            json!({
-                "role": "user",
-                "parts": system_text.iter().map(|s| json!({"text": s})).collect::<Vec<_>>()
-            })
-        } else {
-            json!(null)
-        },
-        "safetySettings": [
-            { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-            { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" }
-        ]
-    });
-    
-    // Wrap in v1internal envelope format (like DroidGravity-Manager)
-    Ok(json!({
-        "project": project_id,
-        "requestId": format!("drovity-{}", uuid::Uuid::new_v4()),
-        "request": inner_request,
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "agent"  // CRITICAL: Must be "agent" not "text" for proper quota!
-    }))
+        return Ok(Json(full_response).into_response());
+    }
 }
 
-fn convert_gemini_to_openai_response(gemini_response: &Value, model: &str) -> Result<Value> {
-    let text = gemini_response["candidates"][0]["content"]["parts"][0]["text"]
-        .as_str()
-        .unwrap_or("");
-    
-    Ok(json!({
-        "id": format!("chatcmpl-{}", uuid::Uuid::new_v4()),
-        "object": "chat.completion",
-        "created": chrono::Utc::now().timestamp(),
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "message": {
-                "role": "assistant",
-                "content": text
-            },
-            "finish_reason": "stop"
-        }],
-        "usage": {
-            "prompt_tokens": 0,
-            "completion_tokens": 0,
-            "total_tokens": 0
+/// Convert the Gemini stream into Responses events (streaming) or a response object
+async fn forward_responses_output(
+    gemini_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+    email: String,
+    model: &str,
+    context: super::openai::responses::ResponsesContext,
+    stream_requested: bool,
+) -> Result<Response> {
+    use axum::body::Body;
+    use futures::StreamExt;
+    
+    let responses_stream = super::openai::responses::create_responses_sse_stream(
+        gemini_stream,
+        trace_id,
+        email.clone(),
+        model.to_string(),
+        context,
+    );
+    
+    if stream_requested {
+        return Ok(axum::response::Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("X-Account-Email", &email)
+            .body(Body::from_stream(responses_stream))
+            .unwrap());
+    }
+    
+    let converted_stream = responses_stream.map(|result| result.map_err(std::io::Error::other));
+    
+    match super::openai::responses::collect_responses_stream(Box::pin(converted_stream)).await {
+        Ok(response) => {
+            tracing::info!("✅ Responses stream collected to JSON");
+            Ok(Json(response).into_response())
         }
-    }))
+        Err(e) => anyhow::bail!("Stream collection error: {}", e),
+    }
 }
@@ -0,0 +1,101 @@
+// Session -> account affinity
+//
+// Consecutive turns of one conversation stay on the same Google account, otherwise
+// thought signatures and the upstream context cache are lost. We only switch (and
+// re-bind) when that account fails.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const AFFINITY_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+const MAX_SESSIONS: usize = 5000;
+
+struct Binding {
+    account_id: String,
+    last_used: Instant,
+}
+
+/// Session key -> account id, with TTL and LRU eviction
+pub struct SessionAffinity {
+    bindings: Mutex<HashMap<String, Binding>>,
+}
+
+impl SessionAffinity {
+    pub fn new() -> Self {
+        Self {
+            bindings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Account currently bound to the session
+    pub fn get(&self, session_key: &str) -> Option<String> {
+        let mut bindings = self.bindings.lock().ok()?;
+        let binding = bindings
+            .get_mut(session_key)
+            .filter(|b| b.last_used.elapsed() <= AFFINITY_TTL)?;
+        binding.last_used = Instant::now();
+        Some(binding.account_id.clone())
+    }
+
+    /// Like `get`, but does not refresh the binding's TTL
+    pub fn peek(&self, session_key: &str) -> Option<String> {
+        let bindings = self.bindings.lock().ok()?;
+        bindings
+            .get(session_key)
+            .filter(|b| b.last_used.elapsed() <= AFFINITY_TTL)
+            .map(|b| b.account_id.clone())
+    }
+
+    /// Bind (or re-bind after failover) the session to an account
+    pub fn bind(&self, session_key: &str, account_id: &str) {
+        if let Ok(mut bindings) = self.bindings.lock() {
+            bindings.insert(
+                session_key.to_string(),
+                Binding {
+                    account_id: account_id.to_string(),
+                    last_used: Instant::now(),
+                },
+            );
+
+            if bindings.len() > MAX_SESSIONS {
+                bindings.retain(|_, b| b.last_used.elapsed() <= AFFINITY_TTL);
+            }
+            while bindings.len() > MAX_SESSIONS {
+                let oldest = bindings
+                    .iter()
+                    .min_by_key(|(_, b)| b.last_used)
+                    .map(|(key, _)| key.clone());
+                match oldest {
+                    Some(key) => bindings.remove(&key),
+                    None => break,
+                };
+            }
+        }
+    }
+}
+
+impl Default for SessionAffinity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_and_rebind() {
+        let affinity = SessionAffinity::new();
+        assert_eq!(affinity.get("session-1"), None);
+
+        affinity.bind("session-1", "account-a");
+        assert_eq!(affinity.get("session-1"), Some("account-a".to_string()));
+        assert_eq!(affinity.get("session-2"), None);
+
+        // Failover re-binds the session
+        affinity.bind("session-1", "account-b");
+        assert_eq!(affinity.get("session-1"), Some("account-b".to_string()));
+    }
+}
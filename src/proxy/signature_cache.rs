@@ -1,10 +1,17 @@
+use rusqlite::{params, Connection, OptionalExtension};
 use std::collections::HashMap;
-use std::sync::{Mutex, OnceLock};
-use std::time::{Duration, SystemTime};
+use std::path::Path;
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // Node.js proxy uses 2 hours TTL
 const SIGNATURE_TTL: Duration = Duration::from_secs(2 * 60 * 60);
 const MIN_SIGNATURE_LENGTH: usize = 50;
+/// Upper bound of rows kept per table in the on-disk cache
+const MAX_PERSISTED_ENTRIES: i64 = 10_000;
+/// Prune the on-disk cache every N writes
+const PRUNE_INTERVAL: u64 = 100;
 
 /// Cache entry with timestamp for TTL
 #[derive(Clone, Debug)]
@@ -21,11 +28,204 @@ impl<T> CacheEntry<T> {
         }
     }
 
+    /// Restore an entry loaded from disk, keeping its original age
+    fn with_unix_timestamp(data: T, secs: i64) -> Self {
+        Self {
+            data,
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64),
+        }
+    }
+
     fn is_expired(&self) -> bool {
         self.timestamp.elapsed().unwrap_or(Duration::ZERO) > SIGNATURE_TTL
     }
 }
 
+/// Tables of the on-disk cache, one per cache layer
+#[derive(Clone, Copy, Debug)]
+enum Table {
+    ToolSignatures,
+    ThinkingFamilies,
+}
+
+impl Table {
+    const ALL: [Table; 2] = [Table::ToolSignatures, Table::ThinkingFamilies];
+
+    fn name(self) -> &'static str {
+        match self {
+            Table::ToolSignatures => "tool_signatures",
+            Table::ThinkingFamilies => "thinking_families",
+        }
+    }
+
+    fn insert_sql(self) -> &'static str {
+        match self {
+            Table::ToolSignatures => "INSERT OR REPLACE INTO tool_signatures (tool_use_id, signature, created_at) VALUES (?1, ?2, ?3)",
+            Table::ThinkingFamilies => "INSERT OR REPLACE INTO thinking_families (signature, family, created_at) VALUES (?1, ?2, ?3)",
+        }
+    }
+
+    fn select_sql(self) -> &'static str {
+        match self {
+            Table::ToolSignatures => "SELECT signature, created_at FROM tool_signatures WHERE tool_use_id = ?1",
+            Table::ThinkingFamilies => "SELECT family, created_at FROM thinking_families WHERE signature = ?1",
+        }
+    }
+}
+
+/// Work for the writer thread
+enum WriteOp {
+    Put { table: Table, key: String, value: String },
+    /// Acknowledged once every earlier write has landed
+    Clear(mpsc::Sender<()>),
+}
+
+/// SQLite backing store (~/.drovity/signatures.db)
+///
+/// Write-through from both layers, read on in-memory miss, so signatures
+/// survive daemon restarts (upgrade, crash, `drovity stop`).
+/// Writes go to a dedicated thread so callers never block on disk I/O.
+/// The global cache is never dropped, so writes still queued when the process
+/// exits (`drovity stop`, Ctrl-C) are lost, as after a crash.
+struct PersistentStore {
+    /// Read connection (WAL lets it run alongside the writer)
+    conn: Mutex<Connection>,
+    writer: Option<mpsc::Sender<WriteOp>>,
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl PersistentStore {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Self::connect(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS tool_signatures (
+                tool_use_id TEXT PRIMARY KEY,
+                signature TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS thinking_families (
+                signature TEXT PRIMARY KEY,
+                family TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );",
+        )?;
+        Self::prune(&conn)?;
+
+        let writer_conn = Self::connect(path)?;
+        let (writer, rx) = mpsc::channel();
+        let writer_thread = std::thread::Builder::new()
+            .name("signature-cache-writer".to_string())
+            .spawn(move || Self::run_writer(writer_conn, rx))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            writer: Some(writer),
+            writer_thread: Some(writer_thread),
+        })
+    }
+
+    fn connect(path: &Path) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(2))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(conn)
+    }
+
+    fn now_secs() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    /// Writer thread: owns its connection until the store is dropped
+    fn run_writer(conn: Connection, rx: mpsc::Receiver<WriteOp>) {
+        let mut writes: u64 = 0;
+        for op in rx {
+            match op {
+                WriteOp::Put { table, key, value } => {
+                    if let Err(e) = conn.execute(table.insert_sql(), params![key, value, Self::now_secs()]) {
+                        tracing::warn!("[SignatureCache] Failed to persist {} entry: {}", table.name(), e);
+                        continue;
+                    }
+                    writes += 1;
+                    if writes.is_multiple_of(PRUNE_INTERVAL) {
+                        if let Err(e) = Self::prune(&conn) {
+                            tracing::warn!("[SignatureCache] Failed to prune persisted cache: {}", e);
+                        }
+                    }
+                }
+                WriteOp::Clear(ack) => {
+                    let _ = conn.execute_batch("DELETE FROM tool_signatures; DELETE FROM thinking_families;");
+                    let _ = ack.send(());
+                }
+            }
+        }
+    }
+
+    /// Queue a write, never blocks the caller
+    fn put(&self, table: Table, key: &str, value: &str) {
+        let op = WriteOp::Put {
+            table,
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        if self.writer.as_ref().is_none_or(|writer| writer.send(op).is_err()) {
+            tracing::warn!("[SignatureCache] Writer thread is gone, {} entry not persisted", table.name());
+        }
+    }
+
+    fn get(&self, table: Table, key: &str) -> Option<CacheEntry<String>> {
+        let conn = self.conn.lock().ok()?;
+        conn.query_row(table.select_sql(), params![key], |row| {
+            Ok(CacheEntry::with_unix_timestamp(row.get::<_, String>(0)?, row.get(1)?))
+        })
+        .optional()
+        .unwrap_or_else(|e| {
+            tracing::warn!("[SignatureCache] Failed to read persisted {} entry: {}", table.name(), e);
+            None
+        })
+        .filter(|entry| !entry.is_expired())
+    }
+
+    /// Drop expired rows and keep only the newest MAX_PERSISTED_ENTRIES per table
+    fn prune(conn: &Connection) -> rusqlite::Result<()> {
+        let cutoff = Self::now_secs() - SIGNATURE_TTL.as_secs() as i64;
+        for table in Table::ALL.map(Table::name) {
+            conn.execute(&format!("DELETE FROM {} WHERE created_at < ?1", table), params![cutoff])?;
+            conn.execute(
+                &format!(
+                    "DELETE FROM {table} WHERE rowid NOT IN \
+                     (SELECT rowid FROM {table} ORDER BY created_at DESC LIMIT ?1)"
+                ),
+                params![MAX_PERSISTED_ENTRIES],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delete every row, waiting for queued writes so none of them resurrect an entry
+    fn clear(&self) {
+        let (ack, done) = mpsc::channel();
+        if let Some(writer) = &self.writer {
+            if writer.send(WriteOp::Clear(ack)).is_ok() {
+                let _ = done.recv();
+            }
+        }
+    }
+}
+
+impl Drop for PersistentStore {
+    /// Flush queued writes (instances from `with_store`; never runs for the global cache)
+    fn drop(&mut self) {
+        drop(self.writer.take());
+        if let Some(handle) = self.writer_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
 /// Double-layer signature cache to handle:
 /// 1. Signature recovery for tool calls (when clients strip them)
 /// 2. Cross-model compatibility checks (preventing Claude signatures on Gemini models)
@@ -39,6 +239,9 @@ pub struct SignatureCache {
     /// Key: thought signature string
     /// Value: Model family identifier (e.g., "claude-3-5-sonnet", "gemini-2.0-flash")
     thinking_families: Mutex<HashMap<String, CacheEntry<String>>>,
+
+    /// Optional on-disk copy of both layers
+    store: Option<PersistentStore>,
 }
 
 impl SignatureCache {
@@ -46,13 +249,47 @@ impl SignatureCache {
         Self {
             tool_signatures: Mutex::new(HashMap::new()),
             thinking_families: Mutex::new(HashMap::new()),
+            store: None,
+        }
+    }
+
+    /// Cache backed by a SQLite file
+    fn with_store(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            store: Some(PersistentStore::open(path)?),
+            ..Self::new()
+        })
+    }
+
+    /// Persistent cache in ~/.drovity, memory-only if the database cannot be opened
+    fn open_default() -> Self {
+        if cfg!(test) {
+            return Self::new();
+        }
+
+        let path = match crate::config::get_config_dir() {
+            Ok(dir) => dir.join("signatures.db"),
+            Err(e) => {
+                tracing::warn!("[SignatureCache] No config dir ({}), signatures will not be persisted", e);
+                return Self::new();
+            }
+        };
+        match Self::with_store(&path) {
+            Ok(cache) => {
+                tracing::debug!("[SignatureCache] Using persistent cache at {}", path.display());
+                cache
+            }
+            Err(e) => {
+                tracing::warn!("[SignatureCache] Failed to open {} ({}), signatures will not be persisted", path.display(), e);
+                Self::new()
+            }
         }
     }
 
     /// Global singleton instance
     pub fn global() -> &'static SignatureCache {
         static INSTANCE: OnceLock<SignatureCache> = OnceLock::new();
-        INSTANCE.get_or_init(SignatureCache::new)
+        INSTANCE.get_or_init(SignatureCache::open_default)
     }
 
     /// Store a tool call signature
@@ -63,6 +300,9 @@ impl SignatureCache {
         
         if let Ok(mut cache) = self.tool_signatures.lock() {
             tracing::debug!("[SignatureCache] Caching tool signature for id: {}", tool_use_id);
+            if let Some(store) = &self.store {
+                store.put(Table::ToolSignatures, tool_use_id, &signature);
+            }
             cache.insert(tool_use_id.to_string(), CacheEntry::new(signature));
             
             // Clean up expired entries occasionally (simple approach: unexpected check)
@@ -75,13 +315,21 @@ impl SignatureCache {
 
     /// Retrieve a signature for a tool_use_id
     pub fn get_tool_signature(&self, tool_use_id: &str) -> Option<String> {
-        if let Ok(cache) = self.tool_signatures.lock() {
+        if let Ok(mut cache) = self.tool_signatures.lock() {
             if let Some(entry) = cache.get(tool_use_id) {
                 if !entry.is_expired() {
                     tracing::debug!("[SignatureCache] Hit tool signature for id: {}", tool_use_id);
                     return Some(entry.data.clone());
                 }
             }
+
+            // Fall back to the on-disk cache (e.g. after a daemon restart)
+            if let Some(entry) = self.store.as_ref().and_then(|s| s.get(Table::ToolSignatures, tool_use_id)) {
+                tracing::debug!("[SignatureCache] Restored tool signature from disk for id: {}", tool_use_id);
+                let signature = entry.data.clone();
+                cache.insert(tool_use_id.to_string(), entry);
+                return Some(signature);
+            }
         }
         None
     }
@@ -94,6 +342,9 @@ impl SignatureCache {
 
         if let Ok(mut cache) = self.thinking_families.lock() {
             tracing::debug!("[SignatureCache] Caching thinking family for sig (len={}): {}", signature.len(), family);
+            if let Some(store) = &self.store {
+                store.put(Table::ThinkingFamilies, &signature, &family);
+            }
             cache.insert(signature, CacheEntry::new(family));
             
             if cache.len() > 1000 {
@@ -104,7 +355,7 @@ impl SignatureCache {
 
     /// Get model family for a signature
     pub fn get_signature_family(&self, signature: &str) -> Option<String> {
-        if let Ok(cache) = self.thinking_families.lock() {
+        if let Ok(mut cache) = self.thinking_families.lock() {
             if let Some(entry) = cache.get(signature) {
                 if !entry.is_expired() {
                     return Some(entry.data.clone());
@@ -112,6 +363,12 @@ impl SignatureCache {
                     tracing::debug!("[SignatureCache] Signature family entry expired");
                 }
             }
+
+            if let Some(entry) = self.store.as_ref().and_then(|s| s.get(Table::ThinkingFamilies, signature)) {
+                let family = entry.data.clone();
+                cache.insert(signature.to_string(), entry);
+                return Some(family);
+            }
         }
         None
     }
@@ -125,13 +382,15 @@ impl SignatureCache {
         if let Ok(mut cache) = self.thinking_families.lock() {
             cache.clear();
         }
+        if let Some(store) = &self.store {
+            store.clear();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread::sleep;
 
     #[test]
     fn test_tool_signature_cache() {
@@ -158,4 +417,52 @@ mod tests {
         cache.cache_thinking_family(sig.clone(), "claude".to_string());
         assert_eq!(cache.get_signature_family(&sig), Some("claude".to_string()));
     }
+
+    #[test]
+    fn test_persisted_across_instances() {
+        let path = std::env::temp_dir().join(format!("drovity-sigcache-{}.db", uuid::Uuid::new_v4()));
+        let sig = "z".repeat(60);
+
+        {
+            let cache = SignatureCache::with_store(&path).unwrap();
+            cache.cache_tool_signature("tool_persist", sig.clone());
+            cache.cache_thinking_family(sig.clone(), "gemini-3-pro".to_string());
+        }
+
+        // Simulates a daemon restart: fresh memory, same database
+        let cache = SignatureCache::with_store(&path).unwrap();
+        assert_eq!(cache.get_tool_signature("tool_persist"), Some(sig.clone()));
+        assert_eq!(cache.get_signature_family(&sig), Some("gemini-3-pro".to_string()));
+
+        // Expired rows are ignored and pruned
+        if let Some(store) = &cache.store {
+            let old = PersistentStore::now_secs() - SIGNATURE_TTL.as_secs() as i64 - 1;
+            store
+                .conn
+                .lock()
+                .unwrap()
+                .execute("UPDATE tool_signatures SET created_at = ?1", params![old])
+                .unwrap();
+        }
+        let restarted = SignatureCache::with_store(&path).unwrap();
+        assert_eq!(restarted.get_tool_signature("tool_persist"), None);
+
+        drop(cache);
+        drop(restarted);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_clear_waits_for_queued_writes() {
+        let path = std::env::temp_dir().join(format!("drovity-sigcache-{}.db", uuid::Uuid::new_v4()));
+        let sig = "w".repeat(60);
+
+        let cache = SignatureCache::with_store(&path).unwrap();
+        cache.cache_tool_signature("tool_cleared", sig);
+        cache.clear();
+        assert_eq!(cache.get_tool_signature("tool_cleared"), None);
+
+        drop(cache);
+        let _ = std::fs::remove_file(&path);
+    }
 }